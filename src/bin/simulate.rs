@@ -0,0 +1,291 @@
+//! Headless simulator: the bot plays many games at accelerated time, then
+//! prints survival time, final length, death reasons and items eaten
+//!
+//! ```text
+//! simulate [--games N] [--size ROWSxCOLS] [--seed N] [--step MS] [--limit SECONDS]
+//!          [--set field=value]...
+//! ```
+//!
+//! `--set` 修改 `GameConfig` 的字段，例如 `--set food_refresh_ms=4000`，用来调整规则，
+//! 字段和取值见 `GameConfig::set`
+
+use snake_lets_go::bot::Bot;
+use snake_lets_go::clock::ManualClock;
+use snake_lets_go::config::GameConfig;
+use snake_lets_go::event::GameEvent;
+use snake_lets_go::outcome::{GameOverReason, WinCondition};
+use snake_lets_go::{Cell, Game};
+
+const USAGE: &str = "usage: simulate [--games N] [--size ROWSxCOLS] [--seed N] [--step MS] \
+                     [--limit SECONDS] [--set field=value]...";
+
+const REASONS: [GameOverReason; 6] = [
+    GameOverReason::SelfCollision,
+    GameOverReason::Starvation,
+    GameOverReason::DoubleWallHit,
+    GameOverReason::DrugOverdose,
+    GameOverReason::HitOtherSnake,
+    GameOverReason::HeadOn,
+];
+
+const CONDITIONS: [WinCondition; 4] = [
+    WinCondition::TargetLength,
+    WinCondition::SurviveDuration,
+    WinCondition::FillBoard,
+    WinCondition::TargetScore,
+];
+
+const CATEGORIES: [&str; 5] = ["food", "fruit", "drink", "snack", "drug"];
+
+struct Options {
+    games: u64,
+    rows: usize,
+    cols: usize,
+    seed: u64,
+    step_ms: i64,
+    limit_ms: i64,
+    config: GameConfig,
+}
+
+fn set_field(config: &mut GameConfig, assignment: &str) -> Result<(), String> {
+    let (field, value) = assignment
+        .split_once('=')
+        .ok_or(format!("expected field=value, found `{}`", assignment))?;
+    config.set(field, value)
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut options = Options {
+        games: 1000,
+        rows: 18,
+        cols: 18,
+        seed: 0,
+        step_ms: 50,
+        limit_ms: 300_000,
+        config: GameConfig::default(),
+    };
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = args
+            .next()
+            .ok_or(format!("missing value of `{}`\n{}", arg, USAGE));
+        let number = |value: String| {
+            value
+                .parse::<u64>()
+                .map_err(|_| format!("bad value `{}` for `{}`", value, arg))
+        };
+        match arg.as_str() {
+            "--games" => options.games = number(value?)?,
+            "--seed" => options.seed = number(value?)?,
+            "--step" => options.step_ms = number(value?)?.max(1) as i64,
+            "--limit" => options.limit_ms = number(value?)? as i64 * 1000,
+            "--size" => {
+                let size = value?;
+                let (rows, cols) = size
+                    .split_once('x')
+                    .and_then(|(rows, cols)| Some((rows.parse().ok()?, cols.parse().ok()?)))
+                    .filter(|&(rows, cols)| rows >= 4 && cols >= 6)
+                    .ok_or(format!("bad size `{}`, expected e.g. 18x24", size))?;
+                options.rows = rows;
+                options.cols = cols;
+            }
+            "--set" => set_field(&mut options.config, &value?)?,
+            "-h" | "--help" => return Err(USAGE.into()),
+            other => return Err(format!("unknown argument `{}`\n{}", other, USAGE)),
+        }
+    }
+    if !options.config.is_valid() {
+        return Err("invalid config".into());
+    }
+    Ok(options)
+}
+
+// 一局的结果
+struct Run {
+    survived_ms: i64,
+    length: u32,
+    score: u32,
+    reason: Option<GameOverReason>, // None 表示获胜或者到时间上限还活着
+    won: Option<WinCondition>,
+    eaten: [u64; 5], // 按 CATEGORIES 计数
+}
+
+fn category(cell: Cell) -> usize {
+    if cell.is_food() {
+        0
+    } else if cell.is_fruit() {
+        1
+    } else if cell.is_drink() {
+        2
+    } else if cell.is_snack() {
+        3
+    } else {
+        4
+    }
+}
+
+fn play(options: &Options, seed: u64) -> Run {
+    let clock = ManualClock::new(0);
+    let mut game = Game::new_with_config_and_clock(
+        options.rows,
+        options.cols,
+        options.config,
+        seed,
+        Box::new(clock.clone()),
+    );
+    let bot = Bot::new(0);
+    let mut eaten = [0; 5];
+    game.start();
+    let mut ts = 0;
+    while ts < options.limit_ms {
+        bot.steer(&mut game);
+        ts += options.step_ms;
+        clock.set(ts);
+        let running = game.tick_at(ts);
+        for (_, event) in game.drain_events() {
+            if let GameEvent::AteItem { cell, .. } = event {
+                eaten[category(cell)] += 1;
+            }
+        }
+        if !running {
+            break;
+        }
+    }
+    Run {
+        survived_ms: ts,
+        length: game.length(),
+        score: game.score(),
+        reason: game.game_over().map(|game_over| game_over.reason),
+        won: game.victory().map(|victory| victory.condition),
+        eaten,
+    }
+}
+
+// 最小值、四分位数、最大值和平均值
+fn distribution(mut values: Vec<f64>) -> String {
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let at = |q: f64| values[((values.len() - 1) as f64 * q).round() as usize];
+    let mean = values.iter().sum::<f64>() / values.len() as f64;
+    format!(
+        "min {:>8.1}  p25 {:>8.1}  median {:>8.1}  p75 {:>8.1}  max {:>8.1}  mean {:>8.1}",
+        at(0.0),
+        at(0.25),
+        at(0.5),
+        at(0.75),
+        at(1.0),
+        mean
+    )
+}
+
+fn main() {
+    let options = match parse_args() {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}", message);
+            std::process::exit(2);
+        }
+    };
+    if options.games == 0 {
+        return;
+    }
+
+    // 每个线程跑一部分种子，结果按种子顺序合并
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get() as u64);
+    let runs: Vec<Run> = std::thread::scope(|scope| {
+        let options = &options;
+        let handles: Vec<_> = (0..threads)
+            .map(|t| {
+                scope.spawn(move || {
+                    (t..options.games)
+                        .step_by(threads as usize)
+                        .map(|i| (i, play(options, options.seed.wrapping_add(i))))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        let mut runs: Vec<(u64, Run)> = handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect();
+        runs.sort_by_key(|&(i, _)| i);
+        runs.into_iter().map(|(_, run)| run).collect()
+    });
+    let games = runs.len() as f64;
+
+    println!(
+        "{} games on {}x{}, seeds {}.., step {} ms, limit {} s",
+        runs.len(),
+        options.rows,
+        options.cols,
+        options.seed,
+        options.step_ms,
+        options.limit_ms / 1000
+    );
+    println!();
+    println!(
+        "survival (s)  {}",
+        distribution(
+            runs.iter()
+                .map(|run| run.survived_ms as f64 / 1000.0)
+                .collect()
+        )
+    );
+    println!(
+        "final length  {}",
+        distribution(runs.iter().map(|run| run.length as f64).collect())
+    );
+    println!(
+        "score         {}",
+        distribution(runs.iter().map(|run| run.score as f64).collect())
+    );
+
+    println!();
+    println!("endings");
+    for reason in REASONS.iter() {
+        let count = runs
+            .iter()
+            .filter(|run| run.reason == Some(*reason))
+            .count();
+        if count > 0 {
+            println!(
+                "  {:<20} {:>6}  {:>5.1}%",
+                format!("{:?}", reason),
+                count,
+                count as f64 * 100.0 / games
+            );
+        }
+    }
+    for condition in CONDITIONS.iter() {
+        let count = runs
+            .iter()
+            .filter(|run| run.won == Some(*condition))
+            .count();
+        if count > 0 {
+            println!(
+                "  {:<20} {:>6}  {:>5.1}%",
+                format!("(won: {:?})", condition),
+                count,
+                count as f64 * 100.0 / games
+            );
+        }
+    }
+    let alive = runs
+        .iter()
+        .filter(|run| run.reason.is_none() && run.won.is_none())
+        .count();
+    if alive > 0 {
+        println!(
+            "  {:<20} {:>6}  {:>5.1}%",
+            "(time limit)",
+            alive,
+            alive as f64 * 100.0 / games
+        );
+    }
+
+    println!();
+    println!("items eaten per game");
+    for (i, name) in CATEGORIES.iter().enumerate() {
+        let total: u64 = runs.iter().map(|run| run.eaten[i]).sum();
+        println!("  {:<20} {:>8.2}", name, total as f64 / games);
+    }
+}
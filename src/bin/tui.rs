@@ -0,0 +1,357 @@
+//! Terminal frontend
+//!
+//! ```text
+//! snake-tui [--size ROWSxCOLS] [--seed N] [--level FILE] [--campaign FILE] [--bot]
+//! ```
+//!
+//! 方向键 / WASD 移动，空格暂停，q 或 Esc 退出，战役模式下过关后按任意键进入下一关
+
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind};
+use crossterm::style::{Color, Print, ResetColor, SetForegroundColor};
+use crossterm::terminal::{self, Clear, ClearType};
+use crossterm::{cursor, execute, queue};
+use snake_lets_go::bot::Bot;
+use snake_lets_go::campaign::{Campaign, StageOutcome};
+use snake_lets_go::clock::WallClock;
+use snake_lets_go::config::GameConfig;
+use snake_lets_go::effect::EffectKind;
+use snake_lets_go::level::Level;
+use snake_lets_go::outcome::{GameOverReason, WinCondition};
+use snake_lets_go::{Cell, Game};
+use std::io::{self, Write};
+use std::time::Duration;
+
+const USAGE: &str =
+    "usage: snake-tui [--size ROWSxCOLS] [--seed N] [--level FILE] [--campaign FILE] [--bot]";
+
+struct Options {
+    rows: usize,
+    cols: usize,
+    seed: u64,
+    level: Option<String>,
+    campaign: Option<String>,
+    bot: bool,
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut options = Options {
+        rows: 18,
+        cols: 18,
+        seed: rand::random(),
+        level: None,
+        campaign: None,
+        bot: false,
+    };
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("missing value of `{}`", arg));
+        match arg.as_str() {
+            "--size" => {
+                let size = value()?;
+                let (rows, cols) = size
+                    .split_once('x')
+                    .and_then(|(rows, cols)| Some((rows.parse().ok()?, cols.parse().ok()?)))
+                    .filter(|&(rows, cols)| rows >= 4 && cols >= 6)
+                    .ok_or(format!("bad size `{}`, expected e.g. 18x24", size))?;
+                options.rows = rows;
+                options.cols = cols;
+            }
+            "--seed" => {
+                let seed = value()?;
+                options.seed = seed.parse().map_err(|_| format!("bad seed `{}`", seed))?;
+            }
+            "--level" => options.level = Some(value()?),
+            "--campaign" => options.campaign = Some(value()?),
+            "--bot" => options.bot = true,
+            "-h" | "--help" => return Err(USAGE.into()),
+            other => return Err(format!("unknown argument `{}`\n{}", other, USAGE)),
+        }
+    }
+    Ok(options)
+}
+
+fn new_game(options: &Options) -> Result<Game, String> {
+    match &options.level {
+        Some(path) => {
+            let source = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+            let level = Level::parse(&source).map_err(|e| format!("{}:{}", path, e))?;
+            Ok(level.build(GameConfig::default(), options.seed, Box::new(WallClock)))
+        }
+        None => Ok(Game::new_with_clock(
+            options.rows,
+            options.cols,
+            options.seed,
+            Box::new(WallClock),
+        )),
+    }
+}
+
+// 每个格子占两列，和 `impl Display for Game` 一样按 Cell 分类
+fn glyph(cell: Cell) -> (&'static str, Color) {
+    match cell {
+        Cell::Blank => ("  ", Color::Reset),
+        Cell::HeadDispenser => ("()", Color::Yellow),
+        Cell::HeadDropper => ("^^", Color::Green),
+        Cell::HeadObserver => ("><", Color::Red),
+        Cell::BodyClay => ("██", Color::Grey),
+        Cell::BodyCoal => ("██", Color::DarkGrey),
+        Cell::BodyDiamond => ("██", Color::Cyan),
+        Cell::BodyDirt => ("██", Color::DarkYellow),
+        Cell::BodyEmerald => ("██", Color::Green),
+        Cell::BodyGold => ("██", Color::Yellow),
+        Cell::BodyIron => ("██", Color::White),
+        Cell::BodyLapis => ("██", Color::Blue),
+        Cell::BodyStone => ("██", Color::Grey),
+        Cell::BodyTNT => ("██", Color::Red),
+        Cell::ObstacleBedrock => ("▓▓", Color::DarkGrey),
+        Cell::ObstacleObsidian => ("▓▓", Color::DarkMagenta),
+        cell if cell.is_snack() => ("::", Color::Magenta),
+        cell if cell.is_fruit() => ("$$", Color::Yellow),
+        cell if cell.is_food() => ("**", Color::DarkYellow),
+        cell if cell.is_drink() => ("~~", Color::Cyan),
+        cell if cell.is_drug() => ("++", Color::Red),
+        _ => ("??", Color::Reset),
+    }
+}
+
+fn reason_text(reason: GameOverReason) -> &'static str {
+    match reason {
+        GameOverReason::SelfCollision => "ate yourself",
+        GameOverReason::Starvation => "starved",
+        GameOverReason::DoubleWallHit => "hit the wall twice",
+        GameOverReason::DrugOverdose => "drug overdose",
+        GameOverReason::HitOtherSnake => "hit another snake",
+        GameOverReason::HeadOn => "head-on collision",
+    }
+}
+
+fn condition_text(condition: WinCondition) -> &'static str {
+    match condition {
+        WinCondition::TargetLength => "reached the target length",
+        WinCondition::SurviveDuration => "survived",
+        WinCondition::FillBoard => "filled the board",
+        WinCondition::TargetScore => "reached the target score",
+    }
+}
+
+// caption 接在状态栏后面
+fn draw(out: &mut impl Write, game: &Game, paused: bool, caption: &str) -> io::Result<()> {
+    let border = "+".to_string() + &"-".repeat(game.cols() * 2) + "+";
+    queue!(out, cursor::MoveTo(0, 0), Print(&border))?;
+    let clouds = game.heal_cloud_cells();
+    for (row, line) in game.board().chunks(game.cols()).enumerate() {
+        queue!(out, cursor::MoveTo(0, row as u16 + 1), Print("|"))?;
+        for (col, &cell) in line.iter().enumerate() {
+            let index = (row * game.cols() + col) as u32;
+            let (text, color) = match cell {
+                // 治疗云下面的空格子
+                Cell::Blank if clouds.contains(&index) => ("··", Color::Red),
+                cell => glyph(cell),
+            };
+            queue!(out, SetForegroundColor(color), Print(text))?;
+        }
+        queue!(out, ResetColor, Print("|"))?;
+    }
+    let bottom = game.rows() as u16 + 1;
+    queue!(out, cursor::MoveTo(0, bottom), Print(&border))?;
+
+    let mut status = format!(
+        "length {}  score {}  hunger {}",
+        game.length(),
+        game.score(),
+        game.hungers()
+    );
+    for effect in game.active_effects(0) {
+        let name = match effect.kind {
+            EffectKind::Hungry => "hungry",
+            EffectKind::Dizziness => "dizzy",
+            EffectKind::HealthBoost => "protected",
+        };
+        status += &match effect.remaining_ms {
+            -1 => format!("  [{}]", name),
+            ms => format!("  [{} {}s]", name, (ms + 999) / 1000),
+        };
+    }
+    status += caption;
+    if paused {
+        status += "  PAUSED (space to resume)";
+    }
+    queue!(
+        out,
+        cursor::MoveTo(0, bottom + 1),
+        Clear(ClearType::CurrentLine),
+        Print(status)
+    )?;
+    if let Some(game_over) = game.game_over() {
+        queue!(
+            out,
+            cursor::MoveTo(0, bottom + 2),
+            SetForegroundColor(Color::Red),
+            Print(format!(
+                "GAME OVER: {} at {} {}, press any key",
+                reason_text(game_over.reason),
+                game_over.row,
+                game_over.col
+            )),
+            ResetColor
+        )?;
+    }
+    if let Some(victory) = game.victory() {
+        queue!(
+            out,
+            cursor::MoveTo(0, bottom + 2),
+            SetForegroundColor(Color::Green),
+            Print(format!(
+                "YOU WIN: {} in {} s, {} items eaten, press any key",
+                condition_text(victory.condition),
+                victory.stats.duration_ms / 1000,
+                victory.stats.items_eaten
+            )),
+            ResetColor
+        )?;
+    }
+    out.flush()
+}
+
+// 退出 (包括 panic) 时恢复终端
+struct RawMode;
+
+impl RawMode {
+    fn enter(out: &mut impl Write) -> io::Result<RawMode> {
+        terminal::enable_raw_mode()?;
+        execute!(out, terminal::EnterAlternateScreen, cursor::Hide)?;
+        Ok(RawMode)
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = execute!(io::stdout(), cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+// 返回 false 表示玩家中途退出
+fn run(game: &mut Game, bot: Option<Bot>, caption: &str) -> io::Result<bool> {
+    let mut out = io::stdout();
+    let _raw = RawMode::enter(&mut out)?;
+    execute!(out, Clear(ClearType::All))?;
+
+    let mut paused = false;
+    game.start();
+    loop {
+        while event::poll(Duration::from_millis(16))? {
+            if let Event::Key(KeyEvent {
+                code,
+                kind: KeyEventKind::Press,
+                ..
+            }) = event::read()?
+            {
+                match code {
+                    KeyCode::Up | KeyCode::Char('w') => game.input(-1, 0),
+                    KeyCode::Down | KeyCode::Char('s') => game.input(1, 0),
+                    KeyCode::Left | KeyCode::Char('a') => game.input(0, -1),
+                    KeyCode::Right | KeyCode::Char('d') => game.input(0, 1),
+                    KeyCode::Char(' ') => {
+                        paused = !paused;
+                        if paused {
+                            game.pause();
+                        } else {
+                            game.start();
+                        }
+                    }
+                    KeyCode::Char('q') | KeyCode::Esc => return Ok(false),
+                    _ => {}
+                }
+            }
+        }
+        if let Some(bot) = &bot {
+            if !paused {
+                bot.steer(game);
+            }
+        }
+        let running = game.tick();
+        draw(&mut out, game, paused, caption)?;
+        if !running {
+            break;
+        }
+    }
+
+    // 等任意键退出
+    loop {
+        if let Event::Key(KeyEvent {
+            kind: KeyEventKind::Press,
+            ..
+        }) = event::read()?
+        {
+            return Ok(true);
+        }
+    }
+}
+
+// 从第一关开始按顺序玩，失败了重玩这一关
+fn run_campaign(path: &str, options: &Options) -> Result<(), String> {
+    let source = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    let mut campaign = Campaign::parse(&source).map_err(|e| format!("{}:{}", path, e))?;
+    let mut stage = 0;
+    let mut seed = options.seed;
+    loop {
+        let mut game = campaign
+            .start_stage_with_clock(stage, seed, Box::new(WallClock))
+            .unwrap();
+        seed = seed.wrapping_add(1);
+        let caption = format!(
+            "  stage {}/{}: {}",
+            stage + 1,
+            campaign.stages().len(),
+            campaign.stages()[stage].name
+        );
+        let bot = if options.bot { Some(Bot::new(0)) } else { None };
+        if !run(&mut game, bot, &caption).map_err(|e| format!("terminal error: {}", e))? {
+            return Ok(());
+        }
+        match campaign.finish(&game) {
+            Some(StageOutcome::Cleared) => stage += 1,
+            Some(StageOutcome::Completed) => {
+                let totals = campaign.totals();
+                println!(
+                    "campaign completed in {} s, score {}, {} items eaten",
+                    totals.duration_ms / 1000,
+                    totals.score,
+                    totals.items_eaten
+                );
+                return Ok(());
+            }
+            _ => {}
+        }
+    }
+}
+
+fn main() {
+    let options = match parse_args() {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}", message);
+            std::process::exit(2);
+        }
+    };
+    if let Some(path) = &options.campaign {
+        if let Err(message) = run_campaign(path, &options) {
+            eprintln!("{}", message);
+            std::process::exit(1);
+        }
+        return;
+    }
+    let mut game = match new_game(&options) {
+        Ok(game) => game,
+        Err(message) => {
+            eprintln!("{}", message);
+            std::process::exit(1);
+        }
+    };
+    let bot = if options.bot { Some(Bot::new(0)) } else { None };
+    if let Err(e) = run(&mut game, bot, "") {
+        eprintln!("terminal error: {}", e);
+        std::process::exit(1);
+    }
+}
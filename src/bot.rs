@@ -0,0 +1,242 @@
+use crate::effect::{EffectKind, Growth};
+use crate::score;
+use crate::{Cell, Game, Snake};
+use std::collections::VecDeque;
+use wasm_bindgen::prelude::*;
+
+const DIRECTIONS: [(i32, i32); 4] = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+
+/// Autopilot for one snake, call `steer` before every `tick`
+///
+/// 每次从蛇下一步必然到达的格子出发做 BFS，找价值最高 (相同时最近) 的物品，
+/// 找不到时往空间最大的方向走
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Bot {
+    player: usize,
+}
+
+impl Bot {
+    /// 希望蛇在下一次移动之后走的方向 (实际方向，还没有考虑头晕反转)，
+    /// 没有安全的方向时返回 None
+    pub fn plan(&self, game: &Game) -> Option<(i32, i32)> {
+        let snake = game.snakes.get(self.player)?;
+        if snake.game_over.is_some() {
+            return None;
+        }
+        // 输入从下一步开始生效，这一步一定沿着 directions[0] 走
+        let current = snake.directions[0];
+        let start = match step(game, snake.head, current) {
+            Some(next) if !blocks(game, snake, next) => next,
+            // 会撞墙，头停在原地，方向直接换成输入的方向
+            _ => snake.head,
+        };
+        let reverse = (-current.0, -current.1);
+
+        // 第一步可以走的方向，先试着直走，少转弯
+        let mut firsts = Vec::new();
+        for &direction in std::iter::once(&current).chain(DIRECTIONS.iter()) {
+            if direction == reverse || firsts.iter().any(|&(d, _)| d == direction) {
+                continue;
+            }
+            if let Some(next) = step(game, start, direction) {
+                if !blocks(game, snake, next) {
+                    firsts.push((direction, next));
+                }
+            }
+        }
+        if firsts.is_empty() {
+            return None;
+        }
+
+        // BFS，记录到达每个格子的第一步方向
+        let mut first_step = vec![None; game.cells.len()];
+        let mut queue = VecDeque::new();
+        first_step[index(game, start)] = Some(current);
+        for &(direction, next) in &firsts {
+            first_step[index(game, next)] = Some(direction);
+            queue.push_back(next);
+        }
+        let mut target: Option<(u32, (i32, i32))> = None;
+        while let Some(pos) = queue.pop_front() {
+            let first = first_step[index(game, pos)].unwrap();
+            let value = value(snake, game.cells[index(game, pos)]);
+            if value > target.map_or(0, |(best, _)| best) {
+                target = Some((value, first));
+            }
+            for &direction in &DIRECTIONS {
+                if let Some(next) = step(game, pos, direction) {
+                    let i = index(game, next);
+                    if first_step[i].is_none() && !blocks(game, snake, next) {
+                        first_step[i] = Some(first);
+                        queue.push_back(next);
+                    }
+                }
+            }
+        }
+
+        // 去目标的路上空间要能装下整条蛇，否则选空间最大的方向
+        let areas: Vec<usize> = firsts
+            .iter()
+            .map(|&(_, next)| area(game, snake, start, next))
+            .collect();
+        if let Some((_, direction)) = target {
+            let i = firsts.iter().position(|&(d, _)| d == direction).unwrap();
+            if areas[i] >= snake.length as usize {
+                return Some(direction);
+            }
+        }
+        let best = (0..firsts.len()).max_by_key(|&i| (areas[i], usize::MAX - i))?;
+        Some(firsts[best].0)
+    }
+}
+
+#[wasm_bindgen]
+impl Bot {
+    #[wasm_bindgen(constructor)]
+    pub fn new(player: usize) -> Bot {
+        Bot { player }
+    }
+
+    pub fn player(&self) -> usize {
+        self.player
+    }
+
+    /// 为这条蛇输入下一步的方向，头晕时会反着输入，只在方向变化时调用 `input_for`
+    /// 上一个输入还在排队时不做新的决定
+    pub fn steer(&self, game: &mut Game) {
+        match game.snakes.get(self.player) {
+            Some(snake) if snake.inputs.is_empty() => {}
+            _ => return,
+        }
+        let direction = match self.plan(game) {
+            Some(direction) => direction,
+            None => return,
+        };
+        let snake = &game.snakes[self.player];
+        // try_move 中头晕时改变方向会被反转，所以反着输入
+        let input = if snake.modifiers().invert_turns && direction != snake.directions[0] {
+            (-direction.0, -direction.1)
+        } else {
+            direction
+        };
+        if input != snake.input_directions {
+            game.input_for(self.player, input.0, input.1);
+        }
+    }
+}
+
+#[inline]
+fn index(game: &Game, (row, col): (i32, i32)) -> usize {
+    game.get_index(row as usize, col as usize)
+}
+
+// 从 pos 往 direction 走一步，出了棋盘返回 None (穿墙模式下绕回来)
+fn step(game: &Game, pos: (i32, i32), direction: (i32, i32)) -> Option<(i32, i32)> {
+    let (row, col) = game.wrap_position((pos.0 + direction.0, pos.1 + direction.1));
+    if row < 0 || col < 0 || row >= game.rows as i32 || col >= game.cols as i32 {
+        None
+    } else {
+        Some((row, col))
+    }
+}
+
+// 不能走的格子: 障碍物、蛇 (包括自己)，以及再吃一次就会死的药
+fn blocks(game: &Game, snake: &Snake, pos: (i32, i32)) -> bool {
+    let cell = game.cells[index(game, pos)];
+    cell.is_obstacle()
+        || cell.is_body()
+        || cell.is_head()
+        || (cell.is_drug() && snake.drugs + 1 >= game.config.drugs_to_die)
+}
+
+// 吃到 cell 的价值，0 表示不值得专门去吃
+fn value(snake: &Snake, cell: Cell) -> u32 {
+    if !cell.is_eatable() {
+        return 0;
+    }
+    if cell.is_drug() {
+        // 治疗药只在有负面 buff 时有用，生命提升只在没有保护时有用
+        let useful = match cell {
+            Cell::DrugSplashHealthBoost => !snake.has_effect(EffectKind::HealthBoost),
+            _ => snake.has_harmful_effect(),
+        };
+        return if useful { score::FOOD_POINTS * 2 } else { 0 };
+    }
+    if cell.is_snack() && snake.modifiers().growth != Growth::Normal {
+        // 饥饿 buff 下吃零食不会变长
+        return 0;
+    }
+    if cell == Cell::SnackSpicyStrip && !snake.modifiers().protected {
+        // 辣条会带来饥饿 buff
+        return 0;
+    }
+    let mut value = score::base_points(cell);
+    if cell == Cell::FruitGoldenApple {
+        value += score::GOLDEN_APPLE_BONUS;
+    }
+    value
+}
+
+// 从 from 出发能到达的格子数，start 视为已经被占据
+fn area(game: &Game, snake: &Snake, start: (i32, i32), from: (i32, i32)) -> usize {
+    let mut seen = vec![false; game.cells.len()];
+    seen[index(game, start)] = true;
+    seen[index(game, from)] = true;
+    let mut stack = vec![from];
+    let mut count = 0;
+    while let Some(pos) = stack.pop() {
+        count += 1;
+        for &direction in &DIRECTIONS {
+            if let Some(next) = step(game, pos, direction) {
+                let i = index(game, next);
+                if !seen[i] && !blocks(game, snake, next) {
+                    seen[i] = true;
+                    stack.push(next);
+                }
+            }
+        }
+    }
+    count
+}
+
+#[cfg(test)]
+mod test {
+    use crate::bot::Bot;
+    use crate::effect::EffectKind;
+    use crate::test::{one_snake_game, put};
+    use crate::Cell;
+
+    #[test]
+    fn test_bot_eats() {
+        let (mut game, clock) = one_snake_game();
+        put(&mut game, 1, 8, Cell::FruitGoldenApple);
+        let bot = Bot::new(0);
+        game.start();
+        for _ in 0..30 {
+            bot.steer(&mut game);
+            clock.advance(300);
+            assert!(game.tick());
+        }
+        assert_eq!(game.length(), 3);
+    }
+
+    #[test]
+    fn test_bot_quirks() {
+        let bot = Bot::new(0);
+
+        // 再吃一次药就会死，药挡在正前方时要绕开
+        let (mut game, _) = one_snake_game();
+        game.snakes[0].drugs = 1;
+        put(&mut game, 5, 5, Cell::DrugSplashHeal);
+        assert_ne!(bot.plan(&game), Some((0, 1)));
+
+        // 头晕时反着输入
+        let (mut game, _) = one_snake_game();
+        put(&mut game, 9, 4, Cell::FoodBread);
+        game.add_effect(0, EffectKind::Dizziness, None, 0);
+        bot.steer(&mut game);
+        assert_eq!(bot.plan(&game), Some((1, 0)));
+        assert_eq!(game.snakes[0].inputs, [(-1, 0)]);
+    }
+}
@@ -0,0 +1,410 @@
+//! Campaign: stages played in order, each with its own board, rules and goal
+//!
+//! ```text
+//! # 注释以 # 开头
+//! stage 第一关              # 关卡名，到下一个 stage 为止都属于这一关
+//! goal length 8            # 过关目标: length N / score N / survive SECONDS
+//! set food_refresh_ms 4000 # 可选，覆盖这一关的规则，字段见 `GameConfig::set`
+//! board 8 12               # 剩下的行是关卡格式，见 `level` 模块
+//! snake 2 4 3 right
+//!
+//! stage 第二关
+//! goal survive 60
+//! board 12 12
+//! ```
+//!
+//! 通过一关才能玩下一关，每一关第一次通过时的统计会累加到 `totals`
+
+use crate::clock::{Clock, WallClock};
+use crate::config::GameConfig;
+use crate::level::{tokenize, Level, LevelError, Parser};
+use crate::outcome::RunStats;
+use crate::Game;
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen]
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GoalKind {
+    Length = 0,
+    Score = 1,
+    Survive = 2,
+}
+
+/// What a stage asks for, `target` of `Survive` is in ms
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Goal {
+    pub kind: GoalKind,
+    pub target: u32,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Stage {
+    pub name: String,
+    pub level: Level,
+    pub config: GameConfig, // 这一关的规则，胜利条件由 goal 决定
+    pub goal: Goal,
+}
+
+impl Stage {
+    // 去掉 config 里原有的胜利条件，换成 goal
+    fn game_config(&self) -> GameConfig {
+        let mut config = GameConfig {
+            target_length: 0,
+            target_score: 0,
+            survive_ms: 0,
+            fill_board: false,
+            ..self.config
+        };
+        match self.goal.kind {
+            GoalKind::Length => config.target_length = self.goal.target,
+            GoalKind::Score => config.target_score = self.goal.target,
+            GoalKind::Survive => config.survive_ms = self.goal.target,
+        }
+        config
+    }
+}
+
+/// How a finished stage went
+#[wasm_bindgen]
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StageOutcome {
+    Cleared = 0,
+    Failed = 1,
+    Completed = 2, // 通过了最后一关
+}
+
+#[wasm_bindgen]
+#[derive(Debug)]
+pub struct Campaign {
+    stages: Vec<Stage>,
+    unlocked: usize,        // 前几关可以玩，至少是 1
+    current: Option<usize>, // 正在玩的关卡
+    cleared: Vec<bool>,     // 每一关是否通过过，重玩通过的关卡不再累计统计
+    totals: RunStats,       // 通过的关卡累计的统计，length 是最近第一次通过的一关的长度
+}
+
+impl Campaign {
+    // 至少要有一关，由 parse 保证
+    pub(crate) fn new(stages: Vec<Stage>) -> Campaign {
+        debug_assert!(!stages.is_empty());
+        Campaign {
+            cleared: vec![false; stages.len()],
+            stages,
+            unlocked: 1,
+            current: None,
+            totals: RunStats {
+                duration_ms: 0,
+                length: 0,
+                score: 0,
+                items_eaten: 0,
+                moves: 0,
+            },
+        }
+    }
+
+    pub fn parse(source: &str) -> Result<Campaign, LevelError> {
+        let mut p = Parser { line: 0 };
+        let lines: Vec<&str> = source.lines().collect();
+        let mut stages = Vec::new();
+        // 当前关卡: 名字、所在行，以及还没确定的目标和规则
+        let mut name: Option<(String, usize)> = None;
+        let mut goal = None;
+        let mut config = GameConfig::default();
+        // 关卡部分的行，其余的行留空，这样关卡的错误行号和整个文件一致
+        let mut level_lines: Vec<&str> = Vec::new();
+
+        for i in 0..=lines.len() {
+            p.line = i + 1;
+            let tokens = if i < lines.len() {
+                tokenize(lines[i])
+            } else {
+                Vec::new()
+            };
+            let keyword = tokens.first().map(|token| token.text);
+            if i == lines.len() || keyword == Some("stage") {
+                // 上一关结束
+                if let Some((name, line)) = name.take() {
+                    let level = Level::parse(&level_lines.join("\n"))?;
+                    p.line = line;
+                    let goal = match goal.take() {
+                        Some(goal) => goal,
+                        None => return p.error(1, "stage has no `goal`"),
+                    };
+                    if !config.is_valid() {
+                        return p.error(1, "stage has invalid rules");
+                    }
+                    stages.push(Stage {
+                        name,
+                        level,
+                        config,
+                        goal,
+                    });
+                    config = GameConfig::default();
+                    p.line = i + 1;
+                }
+                if i == lines.len() {
+                    break;
+                }
+                if tokens.len() < 2 {
+                    let column = tokens[0].column + "stage".len();
+                    return p.error(column, "missing name");
+                }
+                let words: Vec<&str> = tokens[1..].iter().map(|token| token.text).collect();
+                name = Some((words.join(" "), p.line));
+                level_lines = vec![""; i + 1];
+                continue;
+            }
+            if name.is_none() {
+                match tokens.first() {
+                    Some(token) => return p.error(token.column, "expected `stage` first"),
+                    None => continue,
+                }
+            }
+            match keyword {
+                Some("goal") => {
+                    if goal.is_some() {
+                        return p.error(tokens[0].column, "`goal` declared twice");
+                    }
+                    let args = p.args(&tokens, &["goal", "target"])?;
+                    let kind = match args[0].text {
+                        "length" => GoalKind::Length,
+                        "score" => GoalKind::Score,
+                        "survive" => GoalKind::Survive,
+                        other => {
+                            return p.error(args[0].column, format!("unknown goal `{}`", other))
+                        }
+                    };
+                    let target = p.number(&args[1], "target")?;
+                    let target = match kind {
+                        GoalKind::Survive => target.checked_mul(1000),
+                        _ => Some(target),
+                    };
+                    let target =
+                        match target.filter(|&target| target > 0 && target <= u32::MAX as usize) {
+                            Some(target) => target as u32,
+                            None => return p.error(args[1].column, "target is out of range"),
+                        };
+                    goal = Some(Goal { kind, target });
+                    level_lines.push("");
+                }
+                Some("set") => {
+                    let args = p.args(&tokens, &["field", "value"])?;
+                    if let Err(message) = config.set(args[0].text, args[1].text) {
+                        return p.error(args[0].column, message);
+                    }
+                    level_lines.push("");
+                }
+                _ => level_lines.push(lines[i]),
+            }
+        }
+
+        if stages.is_empty() {
+            return p.error(1, "campaign has no `stage`");
+        }
+        Ok(Campaign::new(stages))
+    }
+
+    pub fn stages(&self) -> &[Stage] {
+        &self.stages
+    }
+
+    /// 开始第 `index` 关，还没解锁时返回 None
+    pub fn start_stage_with_clock(
+        &mut self,
+        index: usize,
+        seed: u64,
+        clock: Box<dyn Clock>,
+    ) -> Option<Game> {
+        if index >= self.unlocked {
+            return None;
+        }
+        let stage = &self.stages[index];
+        self.current = Some(index);
+        Some(stage.level.build(stage.game_config(), seed, clock))
+    }
+}
+
+#[wasm_bindgen]
+impl Campaign {
+    /// 从战役文本创建，出错时错误信息带有行号和列号
+    pub fn from_source(source: &str) -> Result<Campaign, JsError> {
+        Ok(Campaign::parse(source)?)
+    }
+
+    pub fn stage_count(&self) -> usize {
+        self.stages.len()
+    }
+
+    pub fn stage_name(&self, index: usize) -> Option<String> {
+        self.stages.get(index).map(|stage| stage.name.clone())
+    }
+
+    pub fn stage_goal(&self, index: usize) -> Option<Goal> {
+        self.stages.get(index).map(|stage| stage.goal)
+    }
+
+    /// 解锁了前几关
+    pub fn unlocked(&self) -> usize {
+        self.unlocked
+    }
+
+    /// 恢复保存下来的进度
+    pub fn unlock(&mut self, count: usize) {
+        self.unlocked = count.clamp(self.unlocked, self.stages.len());
+    }
+
+    /// 正在玩的关卡
+    pub fn current(&self) -> Option<usize> {
+        self.current
+    }
+
+    pub fn totals(&self) -> RunStats {
+        self.totals
+    }
+
+    pub fn start_stage(&mut self, index: usize) -> Result<Game, JsError> {
+        self.start_stage_with_clock(index, rand::random(), Box::new(WallClock))
+            .ok_or_else(|| JsError::new(&format!("stage {} is locked", index)))
+    }
+
+    /// 当前关卡的游戏结束后调用一次，记录结果并解锁下一关
+    /// 游戏还没结束或者没有正在玩的关卡时返回空
+    pub fn finish(&mut self, game: &Game) -> Option<StageOutcome> {
+        let index = self.current?;
+        let victory = game.victory().filter(|victory| victory.player == 0);
+        if victory.is_none() && game.game_over().is_none() && game.player_game_over(0).is_none() {
+            return None;
+        }
+        self.current = None;
+        let stats = match victory {
+            Some(victory) => victory.stats,
+            None => return Some(StageOutcome::Failed),
+        };
+        if !self.cleared[index] {
+            self.cleared[index] = true;
+            self.totals.duration_ms += stats.duration_ms;
+            self.totals.length = stats.length;
+            self.totals.score = self.totals.score.saturating_add(stats.score);
+            self.totals.items_eaten = self.totals.items_eaten.saturating_add(stats.items_eaten);
+            self.totals.moves = self.totals.moves.saturating_add(stats.moves);
+        }
+        if index + 1 == self.stages.len() {
+            return Some(StageOutcome::Completed);
+        }
+        self.unlocked = self.unlocked.max(index + 2);
+        Some(StageOutcome::Cleared)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::campaign::*;
+    use crate::clock::ManualClock;
+    use crate::Cell;
+
+    const CAMPAIGN: &str = "\
+# 测试战役
+stage 热身 关
+goal length 3
+set food_refresh_ms 60000
+board 4 6
+snake 1 3 2 right
+item 1 4 beef
+
+stage 第二关 # 活 10 秒
+goal survive 10
+board 8 8
+";
+
+    #[test]
+    fn test_parse() {
+        let campaign = Campaign::parse(CAMPAIGN).unwrap();
+        let stages = campaign.stages();
+        assert_eq!(stages.len(), 2);
+        assert_eq!(stages[0].name, "热身 关");
+        assert_eq!(
+            stages[0].goal,
+            Goal {
+                kind: GoalKind::Length,
+                target: 3
+            }
+        );
+        assert_eq!(stages[0].config.food_refresh_ms, 60000);
+        assert_eq!(stages[0].level.items, vec![(1, 4, Cell::FoodBeef)]);
+        assert_eq!(stages[1].name, "第二关");
+        assert_eq!(stages[1].goal.target, 10_000);
+        assert_eq!(stages[1].config, GameConfig::default());
+
+        let error = |source: &str| {
+            let e = Campaign::parse(source).unwrap_err();
+            (e.line, e.column, e.message)
+        };
+        assert_eq!(error("board 4 4"), (1, 1, "expected `stage` first".into()));
+        assert_eq!(error("# 空\n"), (2, 1, "campaign has no `stage`".into()));
+        assert_eq!(
+            error("stage a\nboard 4 4\nstage b"),
+            (1, 1, "stage has no `goal`".into())
+        );
+        assert_eq!(
+            error("stage a\ngoal speed 3"),
+            (2, 6, "unknown goal `speed`".into())
+        );
+        assert_eq!(
+            error("stage a\nset speed 3"),
+            (2, 5, "unknown config field `speed`".into())
+        );
+        // 关卡的错误行号是整个文件中的行号
+        assert_eq!(
+            error("stage a\ngoal score 10\nboard 4 4\n\nstage b\ngoal score 10\nboard 4 x"),
+            (7, 9, "expected columns, found `x`".into())
+        );
+    }
+
+    #[test]
+    fn test_progress() {
+        let clock = ManualClock::new(0);
+        let mut campaign = Campaign::parse(CAMPAIGN).unwrap();
+        assert!(campaign
+            .start_stage_with_clock(1, 0, Box::new(clock.clone()))
+            .is_none());
+
+        let mut game = campaign
+            .start_stage_with_clock(0, 0, Box::new(clock.clone()))
+            .unwrap();
+        assert_eq!(campaign.finish(&game), None);
+        game.start();
+        clock.advance(300);
+        assert!(!game.tick());
+        assert_eq!(campaign.finish(&game), Some(StageOutcome::Cleared));
+        assert_eq!(campaign.unlocked(), 2);
+        assert_eq!(campaign.totals().length, 3);
+        assert_eq!(campaign.totals().items_eaten, 1);
+
+        // 重玩通过过的关卡，统计不再累加
+        let mut game = campaign
+            .start_stage_with_clock(0, 0, Box::new(clock.clone()))
+            .unwrap();
+        game.start();
+        clock.advance(300);
+        assert!(!game.tick());
+        assert_eq!(campaign.finish(&game), Some(StageOutcome::Cleared));
+        assert_eq!(campaign.totals().items_eaten, 1);
+
+        // 第二关撞墙两次失败，统计不变
+        let mut game = campaign
+            .start_stage_with_clock(1, 0, Box::new(clock.clone()))
+            .unwrap();
+        game.start();
+        for _ in 0..20 {
+            clock.advance(300);
+            game.tick();
+        }
+        assert!(game.game_over().is_some());
+        assert_eq!(campaign.finish(&game), Some(StageOutcome::Failed));
+        assert_eq!(campaign.totals().items_eaten, 1);
+        assert_eq!(campaign.current(), None);
+    }
+}
@@ -0,0 +1,65 @@
+use chrono::Local;
+use std::cell::Cell;
+use std::rc::Rc;
+
+/// Source of timestamps (ms) that drives a `Game`
+pub trait Clock {
+    fn now(&self) -> i64;
+}
+
+/// Wall clock, used by the browser frontend
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WallClock;
+
+impl Clock for WallClock {
+    #[inline]
+    fn now(&self) -> i64 {
+        Local::now().timestamp_millis()
+    }
+}
+
+/// A clock that only moves when told to, used in tests.
+/// Clones share the same time, so one handle can be given to the
+/// game and the other one kept to drive it.
+#[derive(Clone, Debug, Default)]
+pub struct ManualClock {
+    ts: Rc<Cell<i64>>,
+}
+
+impl ManualClock {
+    pub fn new(ts: i64) -> ManualClock {
+        ManualClock {
+            ts: Rc::new(Cell::new(ts)),
+        }
+    }
+
+    pub fn set(&self, ts: i64) {
+        self.ts.set(ts);
+    }
+
+    pub fn advance(&self, ms: i64) {
+        self.ts.set(self.ts.get() + ms);
+    }
+}
+
+impl Clock for ManualClock {
+    #[inline]
+    fn now(&self) -> i64 {
+        self.ts.get()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::clock::*;
+
+    #[test]
+    fn test_manual_clock() {
+        let clock = ManualClock::new(100);
+        let handle = clock.clone();
+        handle.advance(50);
+        assert_eq!(clock.now(), 150);
+        handle.set(10);
+        assert_eq!(clock.now(), 10);
+    }
+}
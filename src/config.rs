@@ -0,0 +1,147 @@
+use crate::spawn::SpawnStrategyKind;
+use wasm_bindgen::prelude::*;
+
+/// Rule constants of a game, `GameConfig::new()` gives the original rules.
+/// Durations are in ms.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GameConfig {
+    /// 食物刷新间隔
+    pub food_refresh_ms: u32,
+    /// 食物刷新时超过这么久没吃东西算一次饥饿
+    pub starvation_ms: u32,
+    /// 连续饥饿几次加上饥饿 buff
+    pub hungers_to_buff: u32,
+    /// 连续饥饿几次 game over
+    pub hungers_to_die: u32,
+    /// 撞墙后头晕 buff 的持续时间
+    pub dizziness_ms: u32,
+    /// 两次撞墙间隔小于这个值 game over
+    pub double_wall_ms: u32,
+    /// 速度为 1 时的移动间隔，实际间隔为 base_move_interval_ms / speed
+    pub base_move_interval_ms: u32,
+    /// 初始速度，连续吃零食后也恢复成这个速度
+    pub start_speed: u8,
+    /// 连续吃几次正向食物清除饥饿 buff
+    pub goods_to_clear_hunger: u32,
+    /// 连续吃几次正向食物速度翻倍
+    pub goods_to_double_speed: u32,
+    /// 连续吃几次零食速度恢复
+    pub snacks_to_reset_speed: u32,
+    /// 连续吃几次药 game over
+    pub drugs_to_die: u8,
+    /// 穿墙模式: 从一边出去会从对面进来
+    pub wrap: bool,
+    /// 最多排队几个方向输入，每移动一步生效一个，队列满了以后的输入会被丢掉
+    pub input_queue_depth: u8,
+    /// 新物品刷在哪些格子上
+    pub spawn_strategy: SpawnStrategyKind,
+    /// 胜利条件: 长度达到这个值，0 表示不启用
+    pub target_length: u32,
+    /// 胜利条件: 活过这么久，0 表示不启用
+    pub survive_ms: u32,
+    /// 胜利条件: 占满所有不是障碍物的格子
+    pub fill_board: bool,
+    /// 胜利条件: 得分达到这个值，0 表示不启用
+    pub target_score: u32,
+    /// 吃辣条后饥饿 buff 的持续时间
+    pub spicy_hunger_ms: u32,
+    /// 喷溅治疗和治疗云的范围 (以吃到的位置为中心的正方形半径)
+    pub heal_radius: u8,
+    /// 持续治疗留下的治疗云持续多久
+    pub heal_cloud_ms: u32,
+    /// 生命提升的保护持续多久
+    pub health_boost_ms: u32,
+}
+
+impl Default for GameConfig {
+    fn default() -> GameConfig {
+        GameConfig {
+            food_refresh_ms: 5_000,
+            starvation_ms: 5_000,
+            hungers_to_buff: 2,
+            hungers_to_die: 3,
+            dizziness_ms: 20_000,
+            double_wall_ms: 3_000,
+            base_move_interval_ms: 800,
+            start_speed: 3,
+            goods_to_clear_hunger: 4,
+            goods_to_double_speed: 6,
+            snacks_to_reset_speed: 3,
+            drugs_to_die: 2,
+            wrap: false,
+            input_queue_depth: 3,
+            spawn_strategy: SpawnStrategyKind::Uniform,
+            target_length: 0,
+            survive_ms: 0,
+            fill_board: false,
+            target_score: 0,
+            spicy_hunger_ms: 15_000,
+            heal_radius: 2,
+            heal_cloud_ms: 5_000,
+            health_boost_ms: 10_000,
+        }
+    }
+}
+
+impl GameConfig {
+    /// 按字段名修改一个规则，`value` 是文本形式，
+    /// `spawn_strategy` 可以是 uniform、away_from_head、reachable 或 clustered
+    pub fn set(&mut self, field: &str, value: &str) -> Result<(), String> {
+        let bad = || format!("bad value `{}` for `{}`", value, field);
+        let u32_value = || value.parse::<u32>().map_err(|_| bad());
+        let u8_value = || value.parse::<u8>().map_err(|_| bad());
+        match field {
+            "food_refresh_ms" => self.food_refresh_ms = u32_value()?,
+            "starvation_ms" => self.starvation_ms = u32_value()?,
+            "hungers_to_buff" => self.hungers_to_buff = u32_value()?,
+            "hungers_to_die" => self.hungers_to_die = u32_value()?,
+            "dizziness_ms" => self.dizziness_ms = u32_value()?,
+            "double_wall_ms" => self.double_wall_ms = u32_value()?,
+            "base_move_interval_ms" => self.base_move_interval_ms = u32_value()?,
+            "start_speed" => self.start_speed = u8_value()?,
+            "goods_to_clear_hunger" => self.goods_to_clear_hunger = u32_value()?,
+            "goods_to_double_speed" => self.goods_to_double_speed = u32_value()?,
+            "snacks_to_reset_speed" => self.snacks_to_reset_speed = u32_value()?,
+            "drugs_to_die" => self.drugs_to_die = u8_value()?,
+            "wrap" => self.wrap = value.parse().map_err(|_| bad())?,
+            "input_queue_depth" => self.input_queue_depth = u8_value()?,
+            "spawn_strategy" => {
+                self.spawn_strategy = match value {
+                    "uniform" => SpawnStrategyKind::Uniform,
+                    "away_from_head" => SpawnStrategyKind::AwayFromHead,
+                    "reachable" => SpawnStrategyKind::Reachable,
+                    "clustered" => SpawnStrategyKind::Clustered,
+                    _ => return Err(bad()),
+                }
+            }
+            "target_length" => self.target_length = u32_value()?,
+            "survive_ms" => self.survive_ms = u32_value()?,
+            "fill_board" => self.fill_board = value.parse().map_err(|_| bad())?,
+            "target_score" => self.target_score = u32_value()?,
+            "spicy_hunger_ms" => self.spicy_hunger_ms = u32_value()?,
+            "heal_radius" => self.heal_radius = u8_value()?,
+            "heal_cloud_ms" => self.heal_cloud_ms = u32_value()?,
+            "health_boost_ms" => self.health_boost_ms = u32_value()?,
+            _ => return Err(format!("unknown config field `{}`", field)),
+        }
+        Ok(())
+    }
+}
+
+#[wasm_bindgen]
+impl GameConfig {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> GameConfig {
+        GameConfig::default()
+    }
+
+    /// 会导致除零或者一开局就结束的配置是无效的
+    pub fn is_valid(&self) -> bool {
+        self.start_speed > 0
+            && self.goods_to_double_speed > 0
+            && self.hungers_to_die > 0
+            && self.drugs_to_die > 0
+            && self.input_queue_depth > 0
+    }
+}
@@ -0,0 +1,434 @@
+//! Status effects (buffs) on a snake
+//!
+//! 每种效果的规则写在 `EFFECTS` 表里: 最多叠几层、是否能被药清除、
+//! 对速度、转向和生长的影响，以及开始和结束时的钩子
+//!
+//! 持续治疗留下的治疗云也在这里
+
+use crate::event::GameEvent;
+use crate::{Game, Snake};
+use wasm_bindgen::prelude::*;
+
+/// Kinds of status effects, the value is also the bit in `Game::buffs`
+#[wasm_bindgen]
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EffectKind {
+    Hungry = 0,      // 连续饥饿，生长变慢
+    Dizziness = 1,   // 撞墙后头晕，变慢并且转向反转
+    HealthBoost = 2, // 生命提升，期间不会获得负面效果
+}
+
+impl EffectKind {
+    pub(crate) fn from_u8(id: u8) -> Option<EffectKind> {
+        Some(match id {
+            0 => EffectKind::Hungry,
+            1 => EffectKind::Dizziness,
+            2 => EffectKind::HealthBoost,
+            _ => return None,
+        })
+    }
+
+    pub fn def(self) -> &'static EffectDef {
+        &EFFECTS[self as usize]
+    }
+}
+
+/// 吃东西时怎么变长，越往后越慢
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Growth {
+    Normal, // 正向食物长一节，零食每两次长一节
+    Slow,   // 零食不长，正向食物每两次长一节
+}
+
+/// How effects change a snake, combined over all active effects
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Modifiers {
+    /// 速度倍率 (百分比)，多个效果和多层相乘
+    pub speed_percent: u32,
+    /// 改变方向时反过来
+    pub invert_turns: bool,
+    pub growth: Growth,
+    /// 不会获得负面效果
+    pub protected: bool,
+}
+
+impl Modifiers {
+    pub const NONE: Modifiers = Modifiers {
+        speed_percent: 100,
+        invert_turns: false,
+        growth: Growth::Normal,
+        protected: false,
+    };
+
+    /// 修正后的速度，四舍五入，至少为 1
+    pub fn speed(&self, speed: u8) -> u8 {
+        ((speed as u32 * self.speed_percent + 50) / 100).clamp(1, u8::MAX as u32) as u8
+    }
+}
+
+type Hook = fn(&mut Game, usize, i64);
+
+/// Rules of one kind of effect
+pub struct EffectDef {
+    pub kind: EffectKind,
+    pub max_stacks: u8,
+    /// 负面效果，吃药时清除
+    pub harmful: bool,
+    /// 每一层的效果
+    pub modifiers: Modifiers,
+    /// 效果开始时调用，叠层时不调用
+    pub on_apply: Hook,
+    /// 效果过期或者被清除时调用
+    pub on_expire: Hook,
+}
+
+// 下标就是 EffectKind 的值
+const EFFECTS: [EffectDef; 3] = [
+    EffectDef {
+        kind: EffectKind::Hungry,
+        max_stacks: 1,
+        harmful: true,
+        modifiers: Modifiers {
+            growth: Growth::Slow,
+            ..Modifiers::NONE
+        },
+        on_apply: |game, player, ts| game.emit(ts, GameEvent::HungerBuffGained { player }),
+        on_expire: |game, player, ts| game.emit(ts, GameEvent::HungerBuffCleared { player }),
+    },
+    EffectDef {
+        kind: EffectKind::Dizziness,
+        max_stacks: 1,
+        harmful: true,
+        modifiers: Modifiers {
+            speed_percent: 33,
+            invert_turns: true,
+            ..Modifiers::NONE
+        },
+        on_apply: |game, player, ts| game.emit(ts, GameEvent::DizzinessGained { player }),
+        on_expire: |game, player, ts| game.emit(ts, GameEvent::DizzinessCleared { player }),
+    },
+    EffectDef {
+        kind: EffectKind::HealthBoost,
+        max_stacks: 1,
+        harmful: false,
+        modifiers: Modifiers {
+            protected: true,
+            ..Modifiers::NONE
+        },
+        on_apply: |game, player, ts| game.emit(ts, GameEvent::ProtectionGained { player }),
+        on_expire: |game, player, ts| game.emit(ts, GameEvent::ProtectionCleared { player }),
+    },
+];
+
+/// An effect currently on a snake
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ActiveEffect {
+    pub kind: EffectKind,
+    /// 开始或者最近一次叠层的时间
+    pub started_at: i64,
+    /// None 表示一直持续到被清除
+    pub duration_ms: Option<u32>,
+    pub stacks: u8,
+}
+
+impl ActiveEffect {
+    pub fn expires_at(&self) -> Option<i64> {
+        self.duration_ms
+            .map(|duration| self.started_at + duration as i64)
+    }
+}
+
+/// `ActiveEffect` for JS, `remaining_ms` is -1 for effects without a duration
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EffectInfo {
+    pub kind: EffectKind,
+    pub stacks: u8,
+    pub started_at: i64,
+    pub remaining_ms: i64,
+}
+
+/// A cloud left by lingering heal, heals snakes whose head is within
+/// `GameConfig::heal_radius` of it until `expires_at`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HealCloud {
+    pub row: i32,
+    pub col: i32,
+    pub expires_at: i64,
+}
+
+impl Snake {
+    pub(crate) fn has_effect(&self, kind: EffectKind) -> bool {
+        self.effects.iter().any(|effect| effect.kind == kind)
+    }
+
+    pub(crate) fn has_harmful_effect(&self) -> bool {
+        self.effects.iter().any(|effect| effect.kind.def().harmful)
+    }
+
+    pub(crate) fn modifiers(&self) -> Modifiers {
+        let mut modifiers = Modifiers::NONE;
+        for effect in &self.effects {
+            let m = effect.kind.def().modifiers;
+            for _ in 0..effect.stacks {
+                modifiers.speed_percent = modifiers.speed_percent * m.speed_percent / 100;
+            }
+            modifiers.invert_turns |= m.invert_turns;
+            modifiers.growth = modifiers.growth.max(m.growth);
+            modifiers.protected |= m.protected;
+        }
+        modifiers
+    }
+
+    // 旧的 buff 位，只有前 8 种效果有对应的位
+    pub(crate) fn buff_bits(&self) -> u8 {
+        self.effects
+            .iter()
+            .filter(|effect| (effect.kind as u8) < 8)
+            .fold(0, |bits, effect| bits | 1 << effect.kind as u8)
+    }
+}
+
+impl Game {
+    // 给蛇加上效果，已经有了就叠一层并重新计时，受到保护时不会加上负面效果
+    pub(crate) fn add_effect(
+        &mut self,
+        player: usize,
+        kind: EffectKind,
+        duration_ms: Option<u32>,
+        ts: i64,
+    ) {
+        let snake = &mut self.snakes[player];
+        if kind.def().harmful && snake.modifiers().protected {
+            return;
+        }
+        if let Some(effect) = snake.effects.iter_mut().find(|effect| effect.kind == kind) {
+            effect.stacks = (effect.stacks + 1).min(kind.def().max_stacks);
+            effect.started_at = ts;
+            // 没有期限的效果叠层后仍然没有期限
+            effect.duration_ms = effect.duration_ms.and(duration_ms);
+            return;
+        }
+        snake.effects.push(ActiveEffect {
+            kind,
+            started_at: ts,
+            duration_ms,
+            stacks: 1,
+        });
+        (kind.def().on_apply)(self, player, ts);
+    }
+
+    // 去掉蛇身上的效果，返回之前是否有这个效果
+    pub(crate) fn remove_effect(&mut self, player: usize, kind: EffectKind, ts: i64) -> bool {
+        let effects = &mut self.snakes[player].effects;
+        match effects.iter().position(|effect| effect.kind == kind) {
+            Some(i) => {
+                effects.remove(i);
+                (kind.def().on_expire)(self, player, ts);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // 吃药清除所有负面效果
+    pub(crate) fn clear_harmful_effects(&mut self, player: usize, ts: i64) {
+        while let Some(effect) = self.snakes[player]
+            .effects
+            .iter()
+            .find(|effect| effect.kind.def().harmful)
+        {
+            let kind = effect.kind;
+            self.remove_effect(player, kind, ts);
+        }
+    }
+
+    // 喝牛奶清除所有效果，包括正面的
+    pub(crate) fn clear_effects(&mut self, player: usize, ts: i64) {
+        while let Some(effect) = self.snakes[player].effects.first() {
+            let kind = effect.kind;
+            self.remove_effect(player, kind, ts);
+        }
+    }
+
+    // 去掉到期的效果
+    pub(crate) fn expire_effects(&mut self, player: usize, ts: i64) {
+        while let Some(effect) = self.snakes[player].effects.iter().find(|effect| {
+            effect
+                .expires_at()
+                .is_some_and(|expires_at| ts > expires_at)
+        }) {
+            let kind = effect.kind;
+            self.remove_effect(player, kind, ts);
+        }
+    }
+
+    /// 第 `player` 条蛇身上的效果
+    pub fn effects(&self, player: usize) -> &[ActiveEffect] {
+        self.snakes
+            .get(player)
+            .map_or(&[], |snake| snake.effects.as_slice())
+    }
+
+    /// 场上还没散去的治疗云
+    pub fn heal_clouds(&self) -> &[HealCloud] {
+        &self.heal_clouds
+    }
+
+    // pos 是否在以 center 为中心的治疗范围内，穿墙模式下可以绕过去
+    fn in_heal_radius(&self, center: (i32, i32), pos: (i32, i32)) -> bool {
+        let (rows, cols) = (self.rows as i32, self.cols as i32);
+        let mut d_row = (pos.0 - center.0).abs();
+        let mut d_col = (pos.1 - center.1).abs();
+        if self.config.wrap {
+            d_row = d_row.min(rows - d_row);
+            d_col = d_col.min(cols - d_col);
+        }
+        d_row.max(d_col) <= self.config.heal_radius as i32
+    }
+
+    // 清除头部在范围内的所有蛇的负面效果
+    pub(crate) fn heal_around(&mut self, center: (i32, i32), ts: i64) {
+        for player in 0..self.snakes.len() {
+            let snake = &self.snakes[player];
+            if snake.game_over.is_none() && self.in_heal_radius(center, snake.head) {
+                self.clear_harmful_effects(player, ts);
+            }
+        }
+    }
+
+    // 治疗云出现和散去时，它覆盖的格子都要重画
+    fn touch_heal_cloud(&mut self, cloud: HealCloud) {
+        for index in 0..self.rows * self.cols {
+            let pos = ((index / self.cols) as i32, (index % self.cols) as i32);
+            if self.in_heal_radius((cloud.row, cloud.col), pos) {
+                self.touch(index);
+            }
+        }
+    }
+
+    // 持续治疗: 马上治疗一次，并且留下一朵治疗云
+    pub(crate) fn spawn_heal_cloud(&mut self, player: usize, center: (i32, i32), ts: i64) {
+        let cloud = HealCloud {
+            row: center.0,
+            col: center.1,
+            expires_at: ts + self.config.heal_cloud_ms as i64,
+        };
+        self.heal_clouds.push(cloud);
+        self.touch_heal_cloud(cloud);
+        self.emit(
+            ts,
+            GameEvent::HealCloudSpawned {
+                player,
+                row: center.0,
+                col: center.1,
+            },
+        );
+        self.heal_around(center, ts);
+    }
+
+    // 去掉散去的治疗云，剩下的继续治疗
+    pub(crate) fn update_heal_clouds(&mut self, ts: i64) {
+        while let Some(i) = self
+            .heal_clouds
+            .iter()
+            .position(|cloud| ts > cloud.expires_at)
+        {
+            let cloud = self.heal_clouds.remove(i);
+            self.touch_heal_cloud(cloud);
+        }
+        for i in 0..self.heal_clouds.len() {
+            let cloud = self.heal_clouds[i];
+            self.heal_around((cloud.row, cloud.col), ts);
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl Game {
+    /// 第 `player` 条蛇身上的效果和剩余时间，按获得的顺序排列
+    pub fn active_effects(&self, player: usize) -> Vec<EffectInfo> {
+        let now = self.now();
+        self.effects(player)
+            .iter()
+            .map(|effect| EffectInfo {
+                kind: effect.kind,
+                stacks: effect.stacks,
+                started_at: effect.started_at,
+                remaining_ms: effect
+                    .expires_at()
+                    .map_or(-1, |expires_at| (expires_at - now).max(0)),
+            })
+            .collect()
+    }
+
+    /// 被治疗云覆盖的格子下标，用来画出治疗云
+    pub fn heal_cloud_cells(&self) -> Vec<u32> {
+        (0..self.rows * self.cols)
+            .filter(|&index| {
+                let pos = ((index / self.cols) as i32, (index % self.cols) as i32);
+                self.heal_clouds
+                    .iter()
+                    .any(|cloud| self.in_heal_radius((cloud.row, cloud.col), pos))
+            })
+            .map(|index| index as u32)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::clock::ManualClock;
+    use crate::effect::*;
+    use crate::event::GameEvent;
+
+    #[test]
+    fn test_effects() {
+        let mut game = Game::new_with_clock(12, 12, 0, Box::new(ManualClock::new(0)));
+        game.add_effect(0, EffectKind::Dizziness, Some(1000), 0);
+        game.add_effect(0, EffectKind::Hungry, None, 0);
+        assert_eq!(game.buffs(), 0b11);
+        let modifiers = game.snakes[0].modifiers();
+        assert_eq!(modifiers.speed(3), 1);
+        assert!(modifiers.invert_turns);
+        assert_eq!(modifiers.growth, Growth::Slow);
+
+        // 再加一次不会超过最大层数，只重新计时
+        game.add_effect(0, EffectKind::Dizziness, Some(1000), 500);
+        assert_eq!(game.effects(0)[0].stacks, 1);
+        assert_eq!(
+            game.active_effects(0)[0],
+            EffectInfo {
+                kind: EffectKind::Dizziness,
+                stacks: 1,
+                started_at: 500,
+                remaining_ms: 1500,
+            }
+        );
+        assert_eq!(game.active_effects(0)[1].remaining_ms, -1);
+
+        game.expire_effects(0, 1500);
+        assert_eq!(game.effects(0).len(), 2);
+        game.expire_effects(0, 1501);
+        assert_eq!(game.buffs(), 1 << EffectKind::Hungry as u8);
+        game.clear_harmful_effects(0, 1600);
+        assert!(game.effects(0).is_empty());
+        assert_eq!(game.snakes[0].modifiers(), Modifiers::NONE);
+
+        let events: Vec<GameEvent> = game
+            .drain_events()
+            .into_iter()
+            .map(|(_, event)| event)
+            .collect();
+        assert_eq!(
+            events,
+            [
+                GameEvent::DizzinessGained { player: 0 },
+                GameEvent::HungerBuffGained { player: 0 },
+                GameEvent::DizzinessCleared { player: 0 },
+                GameEvent::HungerBuffCleared { player: 0 },
+            ]
+        );
+    }
+}
@@ -0,0 +1,205 @@
+use crate::outcome::{GameOver, Victory};
+use crate::Cell;
+use wasm_bindgen::prelude::*;
+
+/// Something that happened during a tick, for sounds, toasts and animations.
+/// `player` is the snake the event is about
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GameEvent {
+    AteItem {
+        player: usize,
+        cell: Cell,
+        row: i32,
+        col: i32,
+    },
+    GrewLonger {
+        player: usize,
+        length: u32,
+    },
+    SpeedDoubled {
+        player: usize,
+        speed: u8,
+    },
+    SpeedReset {
+        player: usize,
+        speed: u8,
+    },
+    HungerBuffGained {
+        player: usize,
+    },
+    HungerBuffCleared {
+        player: usize,
+    },
+    DizzinessGained {
+        player: usize,
+    },
+    DizzinessCleared {
+        player: usize,
+    },
+    WallKnocked {
+        player: usize,
+        row: i32,
+        col: i32,
+    },
+    FoodRefreshed {
+        count: u32,
+    },
+    /// 一条蛇出局，所有蛇都出局时游戏结束
+    GameOver(GameOver),
+    /// 一条蛇达成了胜利条件，游戏结束
+    Victory(Victory),
+    ProtectionGained {
+        player: usize,
+    },
+    ProtectionCleared {
+        player: usize,
+    },
+    /// 吃到持续治疗，在吃到的位置留下治疗云
+    HealCloudSpawned {
+        player: usize,
+        row: i32,
+        col: i32,
+    },
+}
+
+#[wasm_bindgen]
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EventKind {
+    AteItem = 0,
+    GrewLonger = 1,
+    SpeedDoubled = 2,
+    SpeedReset = 3,
+    HungerBuffGained = 4,
+    HungerBuffCleared = 5,
+    DizzinessGained = 6,
+    DizzinessCleared = 7,
+    WallKnocked = 8,
+    FoodRefreshed = 9,
+    GameOver = 10,
+    Victory = 11,
+    ProtectionGained = 12,
+    ProtectionCleared = 13,
+    HealCloudSpawned = 14,
+}
+
+/// `GameEvent` flattened for JS, fields that don't apply to the kind are
+/// `Cell::Blank` / -1 / 0
+///
+/// 除了 FoodRefreshed 以外 `player` 都是事件所属的蛇
+///
+/// - AteItem: `cell`, `row`, `col`
+/// - GrewLonger: `value` 是新的长度
+/// - SpeedDoubled, SpeedReset: `value` 是新的速度
+/// - WallKnocked: `row`, `col` 是撞墙时头部的位置
+/// - FoodRefreshed: `value` 是刷新的物品个数
+/// - GameOver: `value` 是 `GameOverReason`，`row`, `col` 是头部的位置
+/// - Victory: `value` 是 `WinCondition`
+/// - HealCloudSpawned: `row`, `col` 是治疗云的中心
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Event {
+    pub kind: EventKind,
+    pub ts: i64,
+    pub player: i32,
+    pub cell: Cell,
+    pub row: i32,
+    pub col: i32,
+    pub value: u32,
+}
+
+impl Event {
+    pub fn new(ts: i64, event: GameEvent) -> Event {
+        let mut flat = Event {
+            kind: EventKind::AteItem,
+            ts,
+            player: -1,
+            cell: Cell::Blank,
+            row: -1,
+            col: -1,
+            value: 0,
+        };
+        flat.kind = match event {
+            GameEvent::AteItem {
+                player,
+                cell,
+                row,
+                col,
+            } => {
+                flat.player = player as i32;
+                flat.cell = cell;
+                flat.row = row;
+                flat.col = col;
+                EventKind::AteItem
+            }
+            GameEvent::GrewLonger { player, length } => {
+                flat.player = player as i32;
+                flat.value = length;
+                EventKind::GrewLonger
+            }
+            GameEvent::SpeedDoubled { player, speed } => {
+                flat.player = player as i32;
+                flat.value = speed as u32;
+                EventKind::SpeedDoubled
+            }
+            GameEvent::SpeedReset { player, speed } => {
+                flat.player = player as i32;
+                flat.value = speed as u32;
+                EventKind::SpeedReset
+            }
+            GameEvent::HungerBuffGained { player } => {
+                flat.player = player as i32;
+                EventKind::HungerBuffGained
+            }
+            GameEvent::HungerBuffCleared { player } => {
+                flat.player = player as i32;
+                EventKind::HungerBuffCleared
+            }
+            GameEvent::DizzinessGained { player } => {
+                flat.player = player as i32;
+                EventKind::DizzinessGained
+            }
+            GameEvent::DizzinessCleared { player } => {
+                flat.player = player as i32;
+                EventKind::DizzinessCleared
+            }
+            GameEvent::WallKnocked { player, row, col } => {
+                flat.player = player as i32;
+                flat.row = row;
+                flat.col = col;
+                EventKind::WallKnocked
+            }
+            GameEvent::FoodRefreshed { count } => {
+                flat.value = count;
+                EventKind::FoodRefreshed
+            }
+            GameEvent::GameOver(game_over) => {
+                flat.player = game_over.player as i32;
+                flat.value = game_over.reason as u32;
+                flat.row = game_over.row;
+                flat.col = game_over.col;
+                EventKind::GameOver
+            }
+            GameEvent::Victory(victory) => {
+                flat.player = victory.player as i32;
+                flat.value = victory.condition as u32;
+                EventKind::Victory
+            }
+            GameEvent::ProtectionGained { player } => {
+                flat.player = player as i32;
+                EventKind::ProtectionGained
+            }
+            GameEvent::ProtectionCleared { player } => {
+                flat.player = player as i32;
+                EventKind::ProtectionCleared
+            }
+            GameEvent::HealCloudSpawned { player, row, col } => {
+                flat.player = player as i32;
+                flat.row = row;
+                flat.col = col;
+                EventKind::HealCloudSpawned
+            }
+        };
+        flat
+    }
+}
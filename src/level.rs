@@ -0,0 +1,533 @@
+//! Text level format
+//!
+//! ```text
+//! # 注释以 # 开头
+//! board 8 12             # 行数 列数，必须写在最前面
+//! wrap                   # 可选，穿墙模式
+//! snake 2 4 3 right      # 头部所在行 列，长度，朝向 (up/down/left/right)
+//! snake 6 2 2 left       # 多人游戏每个玩家写一行，最多 4 条
+//! item 5 9 golden_apple  # 初始物品，不会被食物刷新清掉
+//! map                    # 可选，每行 `列数` 个字符: `.` 空白 `#` 基岩 `X` 黑曜石
+//! ............
+//! ..####......
+//! ............
+//! ............
+//! ............
+//! ......X.....
+//! ............
+//! ............
+//! end
+//! ```
+
+use crate::clock::{Clock, WallClock};
+use crate::config::GameConfig;
+use crate::{Cell, Game, MAX_BOARD_SIDE, MAX_PLAYERS};
+use std::convert::TryFrom;
+use std::fmt::{Display, Formatter};
+use wasm_bindgen::prelude::*;
+
+/// A parse error, `line` and `column` start from 1
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LevelError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl Display for LevelError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for LevelError {}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Spawn {
+    pub row: usize,
+    pub col: usize,
+    pub length: u32,
+    pub direction: (i32, i32),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Level {
+    pub rows: usize,
+    pub cols: usize,
+    pub wrap: bool,
+    pub snakes: Vec<Spawn>, // 按玩家编号排列，至少一条
+
+    pub obstacles: Vec<(usize, usize, Cell)>,
+    pub items: Vec<(usize, usize, Cell)>,
+}
+
+// 一个以空白分隔的词，column 是它在行中的位置
+pub(crate) struct Token<'a> {
+    pub(crate) text: &'a str,
+    pub(crate) column: usize,
+}
+
+pub(crate) fn tokenize(line: &str) -> Vec<Token<'_>> {
+    let line = match line.find('#') {
+        Some(comment) => &line[..comment],
+        None => line,
+    };
+    let mut tokens = Vec::new();
+    let mut start = None;
+    for (i, c) in line
+        .char_indices()
+        .chain(std::iter::once((line.len(), ' ')))
+    {
+        match (start, c.is_whitespace()) {
+            (None, false) => start = Some(i),
+            (Some(s), true) => {
+                tokens.push(Token {
+                    text: &line[s..i],
+                    column: line[..s].chars().count() + 1,
+                });
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    tokens
+}
+
+fn item_by_name(name: &str) -> Option<Cell> {
+    Some(match name {
+        "cookie" => Cell::SnackCookie,
+        "dried_kelp" => Cell::SnackDriedKelp,
+        "pumpkin_pie" => Cell::SnackPumpkinPie,
+        "spicy_strip" => Cell::SnackSpicyStrip,
+        "golden_apple" => Cell::FruitGoldenApple,
+        "beef" => Cell::FoodBeef,
+        "bread" => Cell::FoodBread,
+        "cake" => Cell::FoodCake,
+        "chicken" => Cell::FoodChicken,
+        "fish" => Cell::FoodFish,
+        "milk" => Cell::DrinkMilk,
+        "honey" => Cell::DrinkHoney,
+        "lingering_heal" => Cell::DrugLingeringHeal,
+        "splash_heal" => Cell::DrugSplashHeal,
+        "splash_health_boost" => Cell::DrugSplashHealthBoost,
+        _ => return None,
+    })
+}
+
+fn direction_by_name(name: &str) -> Option<(i32, i32)> {
+    Some(match name {
+        "up" => (-1, 0),
+        "down" => (1, 0),
+        "left" => (0, -1),
+        "right" => (0, 1),
+        _ => return None,
+    })
+}
+
+pub(crate) struct Parser {
+    pub(crate) line: usize,
+}
+
+impl Parser {
+    pub(crate) fn error<T>(
+        &self,
+        column: usize,
+        message: impl Into<String>,
+    ) -> Result<T, LevelError> {
+        Err(LevelError {
+            line: self.line,
+            column,
+            message: message.into(),
+        })
+    }
+
+    // 检查参数个数，返回参数
+    pub(crate) fn args<'a, 'b>(
+        &self,
+        tokens: &'b [Token<'a>],
+        names: &[&str],
+    ) -> Result<&'b [Token<'a>], LevelError> {
+        let args = &tokens[1..];
+        if args.len() < names.len() {
+            let column = tokens
+                .last()
+                .map_or(1, |t| t.column + t.text.chars().count());
+            return self.error(column, format!("missing {}", names[args.len()]));
+        }
+        if args.len() > names.len() {
+            return self.error(args[names.len()].column, "unexpected argument");
+        }
+        Ok(args)
+    }
+
+    pub(crate) fn number(&self, token: &Token<'_>, what: &str) -> Result<usize, LevelError> {
+        match token.text.parse() {
+            Ok(n) => Ok(n),
+            Err(_) => self.error(
+                token.column,
+                format!("expected {}, found `{}`", what, token.text),
+            ),
+        }
+    }
+
+    fn position(
+        &self,
+        row: &Token<'_>,
+        col: &Token<'_>,
+        (rows, cols): (usize, usize),
+    ) -> Result<(usize, usize), LevelError> {
+        let (r, c) = (self.number(row, "row")?, self.number(col, "column")?);
+        if r >= rows {
+            return self.error(row.column, format!("row {} is outside the board", r));
+        }
+        if c >= cols {
+            return self.error(col.column, format!("column {} is outside the board", c));
+        }
+        Ok((r, c))
+    }
+}
+
+impl Level {
+    pub fn parse(source: &str) -> Result<Level, LevelError> {
+        let mut p = Parser { line: 0 };
+        let mut board: Option<(usize, usize)> = None;
+        let mut board_at = (0, 0); // `board` 所在的行号和列号，默认的蛇放不下时报在这里
+        let mut snakes: Vec<(Spawn, usize, usize)> = Vec::new(); // 以及所在的行号和列号
+        let mut obstacles = Vec::new();
+        let mut items: Vec<(usize, usize, Cell, usize, usize)> = Vec::new();
+        let mut map_done = false;
+        let mut wrap = false;
+
+        let mut lines = source.lines();
+        while let Some(line) = lines.next() {
+            p.line += 1;
+            let tokens = tokenize(line);
+            let keyword = match tokens.first() {
+                Some(token) => token,
+                None => continue,
+            };
+            if keyword.text != "board" && board.is_none() {
+                return p.error(keyword.column, "expected `board` first");
+            }
+            match keyword.text {
+                "board" => {
+                    if board.is_some() {
+                        return p.error(keyword.column, "`board` declared twice");
+                    }
+                    let args = p.args(&tokens, &["rows", "columns"])?;
+                    let rows = p.number(&args[0], "rows")?;
+                    let cols = p.number(&args[1], "columns")?;
+                    if rows == 0 {
+                        return p.error(args[0].column, "board must have at least 1 row");
+                    }
+                    if cols == 0 {
+                        return p.error(args[1].column, "board must have at least 1 column");
+                    }
+                    if rows > MAX_BOARD_SIDE {
+                        let message = format!("board has more than {} rows", MAX_BOARD_SIDE);
+                        return p.error(args[0].column, message);
+                    }
+                    if cols > MAX_BOARD_SIDE {
+                        let message = format!("board has more than {} columns", MAX_BOARD_SIDE);
+                        return p.error(args[1].column, message);
+                    }
+                    board = Some((rows, cols));
+                    board_at = (p.line, keyword.column);
+                }
+                "snake" => {
+                    if snakes.len() == MAX_PLAYERS {
+                        return p
+                            .error(keyword.column, format!("more than {} snakes", MAX_PLAYERS));
+                    }
+                    let args = p.args(&tokens, &["row", "column", "length", "direction"])?;
+                    let (row, col) = p.position(&args[0], &args[1], board.unwrap())?;
+                    let length = p.number(&args[2], "length")?;
+                    if length == 0 {
+                        return p.error(args[2].column, "length must be at least 1");
+                    }
+                    let length = match u32::try_from(length) {
+                        Ok(length) => length,
+                        Err(_) => return p.error(args[2].column, "length is too large"),
+                    };
+                    let direction = match direction_by_name(args[3].text) {
+                        Some(direction) => direction,
+                        None => {
+                            return p.error(
+                                args[3].column,
+                                format!("unknown direction `{}`", args[3].text),
+                            )
+                        }
+                    };
+                    let spawn = Spawn {
+                        row,
+                        col,
+                        length,
+                        direction,
+                    };
+                    snakes.push((spawn, p.line, keyword.column));
+                }
+                "item" => {
+                    let args = p.args(&tokens, &["row", "column", "item"])?;
+                    let (row, col) = p.position(&args[0], &args[1], board.unwrap())?;
+                    let cell = match item_by_name(args[2].text) {
+                        Some(cell) => cell,
+                        None => {
+                            return p
+                                .error(args[2].column, format!("unknown item `{}`", args[2].text))
+                        }
+                    };
+                    items.push((row, col, cell, p.line, keyword.column));
+                }
+                "wrap" => {
+                    p.args(&tokens, &[])?;
+                    wrap = true;
+                }
+                "map" => {
+                    if map_done {
+                        return p.error(keyword.column, "`map` declared twice");
+                    }
+                    p.args(&tokens, &[])?;
+                    let (rows, cols) = board.unwrap();
+                    let mut row = 0;
+                    loop {
+                        let line = match lines.next() {
+                            Some(line) => line,
+                            None => return p.error(1, "missing `end` of `map`"),
+                        };
+                        p.line += 1;
+                        if line.trim() == "end" {
+                            break;
+                        }
+                        if row == rows {
+                            return p.error(1, format!("map has more than {} rows", rows));
+                        }
+                        let mut col = 0;
+                        for (i, c) in line.trim_end().chars().enumerate() {
+                            let cell = match c {
+                                '.' => Cell::Blank,
+                                '#' => Cell::ObstacleBedrock,
+                                'X' => Cell::ObstacleObsidian,
+                                _ => return p.error(i + 1, format!("unknown map tile `{}`", c)),
+                            };
+                            if col == cols {
+                                return p.error(i + 1, format!("map row is longer than {}", cols));
+                            }
+                            if cell != Cell::Blank {
+                                obstacles.push((row, col, cell));
+                            }
+                            col += 1;
+                        }
+                        if col < cols {
+                            return p.error(col + 1, format!("map row is shorter than {}", cols));
+                        }
+                        row += 1;
+                    }
+                    if row < rows {
+                        return p.error(1, format!("map has fewer than {} rows", rows));
+                    }
+                    map_done = true;
+                }
+                other => return p.error(keyword.column, format!("unknown keyword `{}`", other)),
+            }
+        }
+
+        let (rows, cols) = match board {
+            Some(board) => board,
+            None => return p.error(1, "missing `board`"),
+        };
+        let mut occupied = vec![false; rows * cols];
+        for &(row, col, _) in &obstacles {
+            occupied[row * cols + col] = true;
+        }
+
+        // 整条蛇都要在棋盘内的空白格子上
+        if snakes.is_empty() {
+            let spawn = Spawn {
+                row: rows >> 2,
+                col: 2,
+                length: 2,
+                direction: (0, 1),
+            };
+            snakes.push((spawn, board_at.0, board_at.1));
+        }
+        for &(snake, line, column) in &snakes {
+            p.line = line;
+            for i in 0..snake.length as i64 {
+                let row = snake.row as i64 - i * snake.direction.0 as i64;
+                let col = snake.col as i64 - i * snake.direction.1 as i64;
+                if row < 0 || col < 0 || row >= rows as i64 || col >= cols as i64 {
+                    return p.error(column, "snake does not fit on the board");
+                }
+                let (row, col) = (row as usize, col as usize);
+                if occupied[row * cols + col] {
+                    let what = if obstacles.iter().any(|&(r, c, _)| (r, c) == (row, col)) {
+                        "an obstacle"
+                    } else {
+                        "another snake"
+                    };
+                    return p.error(
+                        column,
+                        format!("snake overlaps {} at {} {}", what, row, col),
+                    );
+                }
+                occupied[row * cols + col] = true;
+            }
+        }
+
+        let mut placed = Vec::with_capacity(items.len());
+        for (row, col, cell, line, column) in items {
+            p.line = line;
+            if occupied[row * cols + col] {
+                return p.error(column, format!("{} {} is already occupied", row, col));
+            }
+            occupied[row * cols + col] = true;
+            placed.push((row, col, cell));
+        }
+
+        Ok(Level {
+            rows,
+            cols,
+            wrap,
+            snakes: snakes.into_iter().map(|(snake, _, _)| snake).collect(),
+            obstacles,
+            items: placed,
+        })
+    }
+
+    /// 按关卡布置一局新游戏，关卡里写了 `wrap` 会覆盖 `config.wrap`
+    pub fn build(&self, mut config: GameConfig, seed: u64, clock: Box<dyn Clock>) -> Game {
+        config.wrap |= self.wrap;
+        let mut game = Game::empty(self.rows, self.cols, config, seed, clock);
+        for &(row, col, cell) in self.obstacles.iter().chain(&self.items) {
+            let index = game.get_index(row, col);
+            game.cells[index] = cell;
+        }
+        for snake in &self.snakes {
+            game.spawn_snake((snake.row, snake.col), snake.length, snake.direction);
+        }
+        game
+    }
+}
+
+#[wasm_bindgen]
+impl Game {
+    /// 从关卡文本创建游戏，出错时错误信息带有行号和列号
+    pub fn from_level(source: &str) -> Result<Game, JsError> {
+        let level = Level::parse(source)?;
+        Ok(level.build(GameConfig::default(), rand::random(), Box::new(WallClock)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::clock::ManualClock;
+    use crate::level::*;
+
+    const LEVEL: &str = "\
+# 测试关卡
+board 4 6
+wrap
+snake 1 3 3 right # 向右
+item 3 5 golden_apple
+map
+......
+......
+.#..X.
+......
+end
+";
+
+    #[test]
+    fn test_parse() {
+        let level = Level::parse(LEVEL).unwrap();
+        assert_eq!((level.rows, level.cols, level.wrap), (4, 6, true));
+        assert_eq!(
+            level.snakes,
+            vec![Spawn {
+                row: 1,
+                col: 3,
+                length: 3,
+                direction: (0, 1)
+            }]
+        );
+        assert_eq!(
+            level.obstacles,
+            vec![
+                (2, 1, Cell::ObstacleBedrock),
+                (2, 4, Cell::ObstacleObsidian)
+            ]
+        );
+        assert_eq!(level.items, vec![(3, 5, Cell::FruitGoldenApple)]);
+
+        let game = level.build(GameConfig::default(), 0, Box::new(ManualClock::new(0)));
+        assert!(game.config.wrap);
+        assert_eq!(game.cells[6], Cell::Blank);
+        assert!(game.cells[7].is_body() && game.cells[8].is_body());
+        assert_eq!(game.cells[9], Cell::HeadDispenser);
+        assert_eq!(game.cells[23], Cell::FruitGoldenApple);
+        assert_eq!(game.snakes[0].tail, (1, 1));
+        assert_eq!(game.snakes[0].length, 3);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = |source: &str| {
+            let e = Level::parse(source).unwrap_err();
+            (e.line, e.column, e.message)
+        };
+        assert_eq!(
+            error("snake 1 1 2 up"),
+            (1, 1, "expected `board` first".into())
+        );
+        assert_eq!(
+            error("board 4 x"),
+            (1, 9, "expected columns, found `x`".into())
+        );
+        assert_eq!(error("board 4"), (1, 8, "missing columns".into()));
+        assert_eq!(
+            error("board 4 4\n\n  snake 1 1 2 north"),
+            (3, 15, "unknown direction `north`".into())
+        );
+        assert_eq!(
+            error("board 4 4\nsnake 1 1 3 right"),
+            (2, 1, "snake does not fit on the board".into())
+        );
+        assert_eq!(
+            error("board 4 4\nsnake 1 1 2 right\nsnake 1 1 2 down"),
+            (3, 1, "snake overlaps another snake at 1 1".into())
+        );
+        assert_eq!(
+            error("board 2 3\nmap\n..\n...\nend"),
+            (3, 3, "map row is shorter than 3".into())
+        );
+        assert_eq!(
+            error("board 2 3\nmap\n..?\n...\nend"),
+            (3, 3, "unknown map tile `?`".into())
+        );
+        assert_eq!(
+            error("board 4 4\nitem 0 9 cake"),
+            (2, 8, "column 9 is outside the board".into())
+        );
+        assert_eq!(
+            error("board 4 4\nitem 1 2 cake"),
+            (2, 1, "1 2 is already occupied".into())
+        );
+        assert_eq!(
+            error("board 4 4\nmap\n....\n"),
+            (3, 1, "missing `end` of `map`".into())
+        );
+        assert_eq!(
+            error("board 100000000 100000000"),
+            (1, 7, "board has more than 256 rows".into())
+        );
+        assert_eq!(
+            error("board 4 4\nsnake 1 1 4294967297 right"),
+            (2, 11, "length is too large".into())
+        );
+        // 默认的蛇放不下，报在 `board` 那一行
+        assert_eq!(
+            error("# 很窄的棋盘\n  board 4 2"),
+            (2, 3, "snake does not fit on the board".into())
+        );
+    }
+}
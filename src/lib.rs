@@ -1,9 +1,31 @@
+use crate::clock::{Clock, WallClock};
+use crate::config::GameConfig;
+use crate::effect::{ActiveEffect, EffectKind, Growth, HealCloud, Modifiers};
+use crate::event::{Event, GameEvent};
+use crate::outcome::{GameOver, GameOverReason, RunStats, Victory, WinCondition};
+use crate::replay::{Action, Replay};
+use crate::rng::GameRng;
+use crate::spawn::{SpawnContext, SpawnStrategy};
 use crate::utils::Timer;
-use chrono::Local;
+use rand::Rng;
 use std::collections::VecDeque;
 use std::fmt::{Display, Formatter};
 use wasm_bindgen::prelude::*;
 
+pub mod bot;
+pub mod campaign;
+pub mod clock;
+pub mod codec;
+pub mod config;
+pub mod effect;
+pub mod event;
+pub mod level;
+pub mod outcome;
+pub mod replay;
+pub mod rng;
+pub mod score;
+mod snapshot;
+pub mod spawn;
 mod utils;
 
 // When the `wee_alloc` feature is enabled, use `wee_alloc` as the global
@@ -81,51 +103,66 @@ pub enum Cell {
     // lingering heal 持续治疗
     DrugSplashHeal = 27,
     // splash heal 瞬间治疗
-    DrugSplashHealthBoost = 28, // splash health boost 生命提升
+    DrugSplashHealthBoost = 28,
+    // splash health boost 生命提升
+    ObstacleBedrock = 29,
+    // bedrock 基岩
+    ObstacleObsidian = 30, // obsidian 黑曜石
 }
 
 impl Cell {
+    // 从字节还原 Cell，超出范围返回 None
+    pub fn from_u8(id: u8) -> Option<Cell> {
+        if id <= Cell::ObstacleObsidian as u8 {
+            // Safety: 0-30 都是合法的 Cell
+            Some(unsafe { core::mem::transmute::<u8, Cell>(id) })
+        } else {
+            None
+        }
+    }
+
     #[inline]
     fn blank(&mut self) {
         *self = Cell::Blank;
     }
 
     #[inline]
-    fn eatable(&mut self) {
-        let prob: u8 = rand::random();
+    fn eatable<R: Rng + ?Sized>(&mut self, rng: &mut R) {
+        let prob: u8 = rng.gen();
         match prob % 5 {
-            0 => self.drug(),
-            1 => self.drink(),
-            2 => self.food(),
+            0 => self.drug(rng),
+            1 => self.drink(rng),
+            2 => self.food(rng),
             3 => self.fruit(),
-            4 => self.snack(),
+            4 => self.snack(rng),
             _ => unreachable!(),
         }
     }
 
     #[inline]
-    fn is_eatable(&self) -> bool {
-        *self as u8 >= 14
+    pub fn is_eatable(&self) -> bool {
+        let id = *self as u8;
+        (14..=28).contains(&id)
     }
 
     // Safety: cast 26-28 to Cell
     #[inline]
-    fn drug(&mut self) {
-        let prob: u8 = rand::random();
+    fn drug<R: Rng + ?Sized>(&mut self, rng: &mut R) {
+        let prob: u8 = rng.gen();
         unsafe {
-            *self = core::mem::transmute((prob % 3) + 26);
+            *self = core::mem::transmute::<u8, Cell>((prob % 3) + 26);
         }
     }
 
     #[inline]
-    fn is_drug(&self) -> bool {
+    pub fn is_drug(&self) -> bool {
         let id = *self as u8;
         (26..=28).contains(&id)
     }
 
     #[inline]
-    fn drink(&mut self) {
-        if rand::random() {
+    fn drink<R: Rng + ?Sized>(&mut self, rng: &mut R) {
+        if rng.gen() {
             *self = Cell::DrinkMilk;
         } else {
             *self = Cell::DrinkHoney;
@@ -133,21 +170,21 @@ impl Cell {
     }
 
     #[inline]
-    fn is_drink(&self) -> bool {
+    pub fn is_drink(&self) -> bool {
         *self == Cell::DrinkHoney || *self == Cell::DrinkMilk
     }
 
     // Safety: cast 19-23 to Cell
     #[inline]
-    fn food(&mut self) {
-        let prob: u8 = rand::random();
+    fn food<R: Rng + ?Sized>(&mut self, rng: &mut R) {
+        let prob: u8 = rng.gen();
         unsafe {
-            *self = core::mem::transmute((prob % 5) + 19);
+            *self = core::mem::transmute::<u8, Cell>((prob % 5) + 19);
         }
     }
 
     #[inline]
-    fn is_food(&self) -> bool {
+    pub fn is_food(&self) -> bool {
         let id = *self as u8;
         (19..=23).contains(&id)
     }
@@ -158,21 +195,21 @@ impl Cell {
     }
 
     #[inline]
-    fn is_fruit(&self) -> bool {
+    pub fn is_fruit(&self) -> bool {
         *self == Cell::FruitGoldenApple
     }
 
     // Safety: cast 14-17 to Cell
     #[inline]
-    fn snack(&mut self) {
-        let prob: u8 = rand::random();
+    fn snack<R: Rng + ?Sized>(&mut self, rng: &mut R) {
+        let prob: u8 = rng.gen();
         unsafe {
-            *self = core::mem::transmute((prob % 4) + 14);
+            *self = core::mem::transmute::<u8, Cell>((prob % 4) + 14);
         }
     }
 
     #[inline]
-    fn is_snack(&self) -> bool {
+    pub fn is_snack(&self) -> bool {
         let id = *self as u8;
         (14..=17).contains(&id)
     }
@@ -199,22 +236,41 @@ impl Cell {
 
     // Safety: cast 1-10 to Cell
     #[inline]
-    fn body(&mut self) {
-        let prob: u8 = rand::random();
+    fn body<R: Rng + ?Sized>(&mut self, rng: &mut R) {
+        let prob: u8 = rng.gen();
         unsafe {
-            *self = core::mem::transmute((prob % 10) + 1);
+            *self = core::mem::transmute::<u8, Cell>((prob % 10) + 1);
         }
     }
 
     #[inline]
-    fn is_body(&self) -> bool {
+    pub fn is_body(&self) -> bool {
         let id = *self as u8;
         (1..=10).contains(&id)
     }
+
+    #[inline]
+    pub fn is_obstacle(&self) -> bool {
+        *self == Cell::ObstacleBedrock || *self == Cell::ObstacleObsidian
+    }
+
+    // 刷新出来的物品能存在多久，相对于食物刷新间隔的百分比
+    #[inline]
+    fn lifetime_percent(&self) -> u64 {
+        match *self {
+            Cell::FruitGoldenApple => 60, // 金苹果很快就会消失
+            Cell::FoodBread => 300,       // 面包放得久
+            cell if cell.is_food() => 150,
+            cell if cell.is_drug() => 80,
+            _ => 100,
+        }
+    }
 }
 
 // just a status container
 pub struct Snake {
+    input_directions: (i32, i32),     // 当前生效的输入方向
+    inputs: VecDeque<(i32, i32)>,     // 还没生效的输入，每移动一步取出一个
     head: (i32, i32),                 // (row, col)
     directions: VecDeque<(i32, i32)>, // [(d_row, d_col)]
     tail: (i32, i32),                 // (row, col, d_row, d_col)
@@ -223,28 +279,99 @@ pub struct Snake {
     drugs: u8,                        // 连续吃药
     hunger: u32,                      // 连续挨饿
     hunger_cnt: u8,                   // 饥饿 buff2 下吃正向食物计数 (不一定需要连续)
-    speed: u8,                        // 速度
-    // 位: 76543210
-    // 0: 是否处于饥饿状态(生长缓慢buff2)
-    // 1: 是否处于头晕状态
-    // 3~7: 保留
-    timing_buff: u8, // buff
+    speed: u8,                        // 速度，实际速度还要乘上效果的倍率
+    effects: Vec<ActiveEffect>,       // 身上的效果 (buff)，按获得的顺序
     length: u32,
-}
-
-const HUNGRY_BUFF: u8 = 0;
-const DIZZINESS_BUFF: u8 = 1;
-
-pub struct Refreshes {
-    // 上次食物刷新时间 (ms)
-    last_eatable_refresh: i64,
-    last_eatable_pos: Vec<usize>,
+    score: u32,
     // 上次吃东西时间 (ms)
     last_feed: i64,
     // 上次创墙时间 (ms)
     last_knock_wall: i64,
     // 上次移动时间 (ms)
     last_move: i64,
+    // 出生时间 (ms)，用来统计存活时间
+    spawned_at: i64,
+    // 吃到的物品数
+    eaten: u32,
+    // 走了多少步
+    moves: u32,
+    // 出局的原因，出局后蛇会从棋盘上移除 (最后一条蛇除外)
+    game_over: Option<GameOver>,
+}
+
+impl Snake {
+    // 还没放到棋盘上的蛇，之后需要设置 head/tail/directions/length
+    fn new(config: &GameConfig, now: i64) -> Snake {
+        Snake {
+            input_directions: (0, 1),
+            inputs: VecDeque::new(),
+            head: (0, 0),
+            directions: VecDeque::new(),
+            tail: (0, 0),
+            goods: 0,
+            snacks: 0,
+            drugs: 0,
+            hunger: 0,
+            hunger_cnt: 0,
+            speed: config.start_speed,
+            effects: Vec::new(),
+            length: 0,
+            score: 0,
+            last_feed: now,
+            // 从未撞墙，时钟不一定从 unix 时间开始
+            last_knock_wall: NEVER,
+            last_move: now,
+            spawned_at: now,
+            eaten: 0,
+            moves: 0,
+            game_over: None,
+        }
+    }
+}
+
+/// `Game::buffs` 中饥饿 buff 所在的位
+pub const HUNGRY_BUFF: u8 = EffectKind::Hungry as u8;
+/// `Game::buffs` 中头晕 buff 所在的位
+pub const DIZZINESS_BUFF: u8 = EffectKind::Dizziness as u8;
+
+/// 同一棋盘上最多的蛇数
+pub const MAX_PLAYERS: usize = 4;
+
+/// 棋盘最多这么多行、这么多列
+pub const MAX_BOARD_SIDE: usize = 256;
+
+/// `Game::owners` 中不属于任何蛇的格子
+pub const NO_OWNER: u8 = u8::MAX;
+
+// 事件队列的上限，前端一直不取的话丢掉最早的事件
+const MAX_EVENTS: usize = 1024;
+
+// 表示某件事从未发生过的时间戳，足够远且相减不会溢出
+const NEVER: i64 = i64::MIN / 2;
+
+// 两次 tick 之间游戏时间最多前进多久，超过的部分当作暂停
+const MAX_TICK_GAP_MS: i64 = 1000;
+
+pub struct Refreshes {
+    // 上次食物刷新时间 (ms)
+    last_eatable_refresh: i64,
+    // 刷新出来的、还没被吃掉也没过期的物品
+    items: Vec<Item>,
+}
+
+// 这一帧要移动的一条蛇
+struct Step {
+    player: usize,
+    next: (i32, i32), // 下一个头部的坐标
+    speed: u8,        // 加上效果之后的速度
+    modifiers: Modifiers,
+}
+
+// 一个会过期的物品，关卡中摆放的初始物品不会过期
+struct Item {
+    pos: usize,
+    spawned_at: i64,
+    expires_at: i64,
 }
 
 enum Status {
@@ -254,156 +381,415 @@ enum Status {
 
 #[wasm_bindgen]
 pub struct Game {
-    size: usize,
-    input_directions: (i32, i32),
-    snake: Snake,
+    rows: usize,
+    cols: usize,
+    config: GameConfig,
+    spawner: Box<dyn SpawnStrategy>, // 默认由 config.spawn_strategy 决定
+    snakes: Vec<Snake>,              // 每个玩家一条蛇，下标就是玩家编号
     refreshes: Refreshes,
+    heal_clouds: Vec<HealCloud>,
     status: Status,
     cells: Vec<Cell>,
+    owners: Vec<u8>, // 每个格子属于哪条蛇，和 cells 一一对应
+    clock: Box<dyn Clock>,
+    seed: u64,
+    rng: GameRng,              // 所有随机数都从这里取，种子相同则物品和身体材质完全相同
+    recording: Option<Replay>, // 第一次 input/start/pause/tick 时开始录像
+    elapsed: i64,              // 游戏时间 (ms)，只在进行中前进，所有计时器都以它为准
+    last_clock: i64,           // 上次推进游戏时间时时钟的读数
+    game_over: Option<GameOver>,
+    victory: Option<Victory>,
+    events: VecDeque<(i64, GameEvent)>, // 还没被取走的事件
+    dirty: Vec<u32>,                    // 上次 take_dirty 之后变过的格子
+    dirty_mark: Vec<bool>,              // 和 cells 一一对应，是否已经在 dirty 中
 }
 
 #[inline]
-fn get_index(size: usize, row: usize, column: usize) -> usize {
-    row * size + column
+fn get_index(cols: usize, row: usize, column: usize) -> usize {
+    row * cols + column
 }
 
-#[wasm_bindgen]
 impl Game {
-    pub fn new_with_size(size: usize) -> Game {
+    /// 使用指定的种子和时钟创建游戏，测试中可以传入 `ManualClock`
+    pub fn new_with_clock(rows: usize, cols: usize, seed: u64, clock: Box<dyn Clock>) -> Game {
+        Game::new_with_config_and_clock(rows, cols, GameConfig::default(), seed, clock)
+    }
+
+    pub fn new_with_config_and_clock(
+        rows: usize,
+        cols: usize,
+        config: GameConfig,
+        seed: u64,
+        clock: Box<dyn Clock>,
+    ) -> Game {
+        Game::new_multiplayer_with_clock(rows, cols, 1, config, seed, clock)
+    }
+
+    /// `players` 条蛇共享同一个棋盘，依次从四个角附近出发
+    ///
+    /// `players` 会被限制在 1 到 `MAX_PLAYERS` 之间，放不下这么多蛇的棋盘会被放大
+    /// (一条蛇至少 1 行 3 列，多条蛇至少 2 行 6 列)，行数和列数最多是 `MAX_BOARD_SIDE`
+    pub fn new_multiplayer_with_clock(
+        rows: usize,
+        cols: usize,
+        players: usize,
+        config: GameConfig,
+        seed: u64,
+        clock: Box<dyn Clock>,
+    ) -> Game {
+        let players = players.clamp(1, MAX_PLAYERS);
+        let (min_rows, min_cols) = if players == 1 { (1, 3) } else { (2, 6) };
+        let rows = rows.clamp(min_rows, MAX_BOARD_SIDE);
+        let cols = cols.clamp(min_cols, MAX_BOARD_SIDE);
+        let mut game = Game::empty(rows, cols, config, seed, clock);
+        let (top, bottom) = (rows >> 2, rows - 1 - (rows >> 2));
+        let spawns = [
+            ((top, 2), (0, 1)),
+            ((bottom, cols - 3), (0, -1)),
+            ((bottom, 2), (0, 1)),
+            ((top, cols - 3), (0, -1)),
+        ];
+        for &(head, direction) in spawns.iter().take(players) {
+            game.spawn_snake(head, 2, direction);
+        }
+        game
+    }
+
+    // 没有蛇的空棋盘，之后需要调用 spawn_snake
+    pub(crate) fn empty(
+        rows: usize,
+        cols: usize,
+        config: GameConfig,
+        seed: u64,
+        clock: Box<dyn Clock>,
+    ) -> Game {
         utils::set_panic_hook();
-        let mut cells: Vec<_> = (0..size * size).map(|_| Cell::Blank).collect();
-        let head_row = size >> 2;
-        let head_col = 2;
-        let now = Local::now().timestamp_millis();
-        cells[get_index(size, head_row, head_col)].head_normal();
-        cells[get_index(size, head_row, head_col - 1)].body();
+        debug_assert!(config.is_valid());
+        debug_assert!(rows <= MAX_BOARD_SIDE && cols <= MAX_BOARD_SIDE);
         Game {
-            size,
-            cells,
-            snake: Snake {
-                head: (head_row as i32, head_col as i32),
-                directions: vec![(0, 1), (0, 1)].into(),
-                tail: (head_row as i32, (head_col - 1) as i32),
-                goods: 0,
-                snacks: 0,
-                drugs: 0,
-                hunger: 0,
-                hunger_cnt: 0,
-                speed: 3,
-                timing_buff: 0,
-                length: 2,
-            },
+            rows,
+            cols,
+            config,
+            spawner: config.spawn_strategy.strategy(),
+            cells: vec![Cell::Blank; rows * cols],
+            owners: vec![NO_OWNER; rows * cols],
+            snakes: Vec::new(),
             refreshes: Refreshes {
-                last_eatable_refresh: 0,
-                last_eatable_pos: Vec::new(),
-                last_feed: now,
-                last_knock_wall: 0,
-                last_move: now,
+                // 从未刷新，时钟不一定从 unix 时间开始
+                last_eatable_refresh: NEVER,
+                items: Vec::new(),
             },
             status: Status::Pause,
-            input_directions: (0, 1),
+            clock,
+            seed,
+            rng: GameRng::new(seed),
+            recording: None,
+            elapsed: 0,
+            last_clock: 0,
+            game_over: None,
+            victory: None,
+            heal_clouds: Vec::new(),
+            events: VecDeque::new(),
+            // 第一次取的时候整个棋盘都算变过
+            dirty: (0..(rows * cols) as u32).collect(),
+            dirty_mark: vec![true; rows * cols],
+        }
+    }
+
+    // 放置一条头部在 head，朝 direction 方向，长为 length 的蛇，作为下一个玩家
+    // 调用者需要保证整条蛇都在棋盘内的空白格子上
+    pub(crate) fn spawn_snake(&mut self, head: (usize, usize), length: u32, direction: (i32, i32)) {
+        debug_assert!(length > 0);
+        debug_assert!(self.snakes.len() < MAX_PLAYERS);
+        let player = self.snakes.len() as u8;
+        let head = (head.0 as i32, head.1 as i32);
+        let mut tail = head;
+        let index = self.get_index(head.0 as usize, head.1 as usize);
+        self.cells[index].head_normal();
+        self.owners[index] = player;
+        self.touch(index);
+        for _ in 1..length {
+            tail = (tail.0 - direction.0, tail.1 - direction.1);
+            let index = self.get_index(tail.0 as usize, tail.1 as usize);
+            self.cells[index].body(&mut self.rng);
+            self.owners[index] = player;
+            self.touch(index);
+        }
+        let mut snake = Snake::new(&self.config, self.now());
+        snake.head = head;
+        snake.tail = tail;
+        snake.directions = vec![direction; length as usize].into();
+        snake.length = length;
+        snake.input_directions = direction;
+        self.snakes.push(snake);
+    }
+
+    // 穿墙模式下把坐标绕回棋盘内，否则原样返回
+    #[inline]
+    fn wrap_position(&self, (row, col): (i32, i32)) -> (i32, i32) {
+        if self.config.wrap {
+            (
+                row.rem_euclid(self.rows as i32),
+                col.rem_euclid(self.cols as i32),
+            )
+        } else {
+            (row, col)
+        }
+    }
+
+    // 记录一个变过的格子 (包括 owners 的变化)
+    #[inline]
+    fn touch(&mut self, index: usize) {
+        if !self.dirty_mark[index] {
+            self.dirty_mark[index] = true;
+            self.dirty.push(index as u32);
+        }
+    }
+
+    // 记录一个事件
+    #[inline]
+    fn emit(&mut self, ts: i64, event: GameEvent) {
+        if self.events.len() == MAX_EVENTS {
+            self.events.pop_front();
+        }
+        self.events.push_back((ts, event));
+    }
+
+    /// 按行存储的棋盘，共 rows * cols 个
+    pub fn board(&self) -> &[Cell] {
+        &self.cells
+    }
+
+    /// 取走上次调用之后发生的所有事件
+    pub fn drain_events(&mut self) -> Vec<(i64, GameEvent)> {
+        self.events.drain(..).collect()
+    }
+
+    // 当前的游戏时间
+    #[inline]
+    fn now(&self) -> i64 {
+        self.elapsed
+    }
+
+    // 记录一次调用，第一次调用时保存当前状态作为录像的起点
+    fn record(&mut self, ts: i64, action: Action) {
+        let (rows, cols, seed) = (self.rows, self.cols, self.seed);
+        if self.recording.is_none() {
+            let initial = self.encode();
+            self.recording = Some(Replay::new(rows, cols, seed, ts, initial));
+        }
+        if let Some(recording) = &mut self.recording {
+            recording.record(ts, action);
+        }
+    }
+
+    // 已经开始录像，或者是读档恢复的进行中的游戏 (存档里没有录像)
+    fn started(&self) -> bool {
+        self.recording.is_some() || self.elapsed > 0
+    }
+
+    /// 替换驱动 `tick` 的时钟，游戏时间线保持连续
+    pub fn set_clock(&mut self, clock: Box<dyn Clock>) {
+        self.last_clock = clock.now();
+        self.clock = clock;
+    }
+
+    /// 使用自定义的刷新策略，存档和录像中只记录 `config.spawn_strategy`
+    pub fn set_spawn_strategy(&mut self, spawner: Box<dyn SpawnStrategy>) {
+        self.spawner = spawner;
+    }
+}
+
+#[wasm_bindgen]
+impl Game {
+    pub fn new_with_size(size: usize) -> Game {
+        Game::new_with_dimensions(size, size)
+    }
+
+    /// rows 行 cols 列的棋盘
+    pub fn new_with_dimensions(rows: usize, cols: usize) -> Game {
+        Game::new_with_clock(rows, cols, rand::random(), Box::new(WallClock))
+    }
+
+    /// 相同的种子会得到相同的物品刷新和身体材质
+    pub fn new_with_seed(size: usize, seed: u64) -> Game {
+        Game::new_with_dimensions_and_seed(size, size, seed)
+    }
+
+    /// rows 行 cols 列的棋盘，使用指定的种子
+    pub fn new_with_dimensions_and_seed(rows: usize, cols: usize, seed: u64) -> Game {
+        Game::new_with_clock(rows, cols, seed, Box::new(WallClock))
+    }
+
+    /// 使用自定义规则创建游戏，配置无效 (见 `GameConfig::is_valid`) 时报错
+    pub fn new_with_config(rows: usize, cols: usize, config: GameConfig) -> Result<Game, JsError> {
+        if !config.is_valid() {
+            return Err(JsError::new("invalid game config"));
         }
+        Ok(Game::new_with_config_and_clock(
+            rows,
+            cols,
+            config,
+            rand::random(),
+            Box::new(WallClock),
+        ))
+    }
+
+    /// 本地多人游戏，`players` 会被限制在 1 到 `MAX_PLAYERS` (4) 之间
+    pub fn new_multiplayer(rows: usize, cols: usize, players: usize) -> Game {
+        Game::new_multiplayer_with_clock(
+            rows,
+            cols,
+            players,
+            GameConfig::default(),
+            rand::random(),
+            Box::new(WallClock),
+        )
+    }
+
+    pub fn config(&self) -> GameConfig {
+        self.config
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
     }
 
     #[inline]
     fn get_index(&self, row: usize, column: usize) -> usize {
-        get_index(self.size, row, column)
+        get_index(self.cols, row, column)
     }
 
+    // 移除过期的物品
     #[inline]
-    fn try_refresh_food(&mut self, ts: i64) -> bool {
-        debug_assert!(ts > self.refreshes.last_eatable_refresh);
-        debug_assert!(ts > self.refreshes.last_feed);
-        if ts - self.refreshes.last_eatable_refresh > 5_000 {
-            // 移除上一次的食物
-            for &pos in &self.refreshes.last_eatable_pos {
+    fn expire_items(&mut self, ts: i64) {
+        let mut i = 0;
+        while i < self.refreshes.items.len() {
+            let item = &self.refreshes.items[i];
+            if ts > item.expires_at {
+                let pos = item.pos;
+                self.refreshes.items.swap_remove(i);
                 if self.cells[pos].is_eatable() {
-                    // 判断一下还是不是食物，吃掉的就不删了
                     self.cells[pos].blank();
+                    self.touch(pos);
                 }
-            }
-
-            // 刷新下一次的食物
-            self.refreshes.last_eatable_pos = Vec::new();
-            let eatable_num: u8 = rand::random();
-            while self.refreshes.last_eatable_pos.len() < ((eatable_num % 3) + 3) as usize {
-                let pos: usize = rand::random();
-                let pos = pos % (self.size * self.size);
-                if self.cells[pos] == Cell::Blank {
-                    self.cells[pos].eatable();
-                    self.refreshes.last_eatable_pos.push(pos);
-                }
-            }
-
-            // 食物刷新时判断一下 进食时间是否超过 5s
-            if ts - self.refreshes.last_feed > 5_000 {
-                // 连续饥饿 +1
-                self.snake.hunger += 1;
             } else {
-                // 连续饥饿清零
-                self.snake.hunger = 0;
+                i += 1;
             }
-            if self.snake.hunger == 2 {
-                // 连续 2 次饥饿，加上饥饿 buff
-                self.snake.timing_buff |= 1 << HUNGRY_BUFF;
-            }
-            if self.snake.hunger == 3 {
-                // 连续 3 次饥饿，game over
-                return false;
-            }
-
-            self.refreshes.last_eatable_refresh = ts;
         }
-        true
     }
 
-    #[inline]
-    fn check_buff(&mut self, ts: i64) {
-        debug_assert!(ts > self.refreshes.last_knock_wall);
-        if ts - self.refreshes.last_knock_wall > 20_000 {
-            // 超过 20s 取消 buff
-            self.clear_dizziness_buff();
-        }
+    // 在空白格子 pos 上刷新一个随机物品，存在时间由物品种类决定
+    fn spawn_item(&mut self, pos: usize, ts: i64) {
+        self.cells[pos].eatable(&mut self.rng);
+        self.touch(pos);
+        let lifetime =
+            self.config.food_refresh_ms as u64 * self.cells[pos].lifetime_percent() / 100;
+        self.refreshes.items.push(Item {
+            pos,
+            spawned_at: ts,
+            expires_at: ts.saturating_add(lifetime as i64),
+        });
     }
 
+    // 到时间了就刷新食物，返回这次是否刷新了
     #[inline]
-    fn clear_dizziness_buff(&mut self) {
-        if self.snake.timing_buff & (1 << DIZZINESS_BUFF) > 0 {
-            self.snake.speed *= 3;
-            self.snake.timing_buff &= !(1 << DIZZINESS_BUFF);
+    fn try_refresh_food(&mut self, ts: i64) -> bool {
+        debug_assert!(ts >= self.refreshes.last_eatable_refresh);
+        if ts - self.refreshes.last_eatable_refresh > self.config.food_refresh_ms as i64 {
+            // 刷新新的食物，之前的物品到了各自的时间才会消失
+            let eatable_num: u8 = self.rng.gen();
+            let count = (eatable_num % 3) + 3;
+            let heads: Vec<(i32, i32)> = self
+                .snakes
+                .iter()
+                .filter(|snake| snake.game_over.is_none())
+                .map(|snake| snake.head)
+                .collect();
+            let ctx = SpawnContext {
+                rows: self.rows,
+                cols: self.cols,
+                cells: &self.cells,
+                heads: &heads,
+                wrap: self.config.wrap,
+            };
+            // 空白格子不够时少刷几个
+            let picked = self.spawner.pick(&ctx, count as usize, &mut self.rng);
+            for &pos in &picked {
+                debug_assert!(self.cells[pos] == Cell::Blank);
+                self.spawn_item(pos, ts);
+            }
+            self.emit(
+                ts,
+                GameEvent::FoodRefreshed {
+                    count: picked.len() as u32,
+                },
+            );
+            self.refreshes.last_eatable_refresh = ts;
+            return true;
         }
+        false
     }
 
+    // 食物刷新时判断一下 进食时间是否超过 starvation_ms (默认 5s)
     #[inline]
-    fn clear_hunger_buff(&mut self) {
-        if self.snake.timing_buff & (1 << HUNGRY_BUFF) > 0 {
-            self.snake.timing_buff &= !(1 << HUNGRY_BUFF);
+    fn check_hunger(&mut self, player: usize, ts: i64) -> Result<(), GameOverReason> {
+        debug_assert!(ts >= self.snakes[player].last_feed);
+        if ts - self.snakes[player].last_feed > self.config.starvation_ms as i64 {
+            // 连续饥饿 +1
+            self.snakes[player].hunger += 1;
+        } else {
+            // 连续饥饿清零
+            self.snakes[player].hunger = 0;
+        }
+        if self.snakes[player].hunger == self.config.hungers_to_buff {
+            // 连续 2 次饥饿，加上饥饿 buff (吃辣条得到的饥饿 buff 会变成一直持续)
+            self.add_effect(player, EffectKind::Hungry, None, ts);
+        }
+        if self.snakes[player].hunger >= self.config.hungers_to_die {
+            // 连续 3 次饥饿，game over
+            return Err(GameOverReason::Starvation);
         }
+        Ok(())
     }
 
     // try_move: 这一部分比较难理解，我尽可能地加上注释
+    // 决定这条蛇这一帧是否移动、移动到哪里，撞墙时原地不动
     #[inline]
-    fn try_move(&mut self, ts: i64) -> bool {
-        debug_assert!(ts > self.refreshes.last_move);
-        debug_assert!(ts > self.refreshes.last_knock_wall);
-        let wait_ts = 800 / (self.snake.speed as i64);
-        if ts - self.refreshes.last_move > wait_ts {
-            self.refreshes.last_move = ts;
+    fn try_move(&mut self, player: usize, ts: i64) -> Result<Option<Step>, GameOverReason> {
+        debug_assert!(ts >= self.snakes[player].last_move);
+        debug_assert!(ts >= self.snakes[player].last_knock_wall);
+        let modifiers = self.snakes[player].modifiers();
+        let speed = modifiers.speed(self.snakes[player].speed);
+        let wait_ts = self.config.base_move_interval_ms as i64 / speed as i64;
+        if ts - self.snakes[player].last_move > wait_ts {
+            self.snakes[player].last_move = ts;
+            // 每一步取出一个排队的输入
+            if let Some(input) = self.snakes[player].inputs.pop_front() {
+                self.snakes[player].input_directions = input;
+            }
 
-            let head = self.snake.head;
-            let head_direction = self.snake.directions[0];
-            let next_head = (head.0 + head_direction.0, head.1 + head_direction.1);
+            let head = self.snakes[player].head;
+            let head_direction = self.snakes[player].directions[0];
+            let next_head =
+                self.wrap_position((head.0 + head_direction.0, head.1 + head_direction.1));
 
             // 更新新的头部方向 (下一个头部的方向)
-            let mut next_direction = if self.snake.timing_buff & (1 << DIZZINESS_BUFF) > 0
-                && (head_direction.0 != self.input_directions.0
-                    || head_direction.1 != self.input_directions.1)
+            let mut next_direction = if modifiers.invert_turns
+                && (head_direction.0 != self.snakes[player].input_directions.0
+                    || head_direction.1 != self.snakes[player].input_directions.1)
             {
                 // 如果当前方向改变了 (input_directions不是之前的head_direction)
                 // 并且处于 DIZZINESS_BUFF 状态，下一次的输入方向反转
-                (-self.input_directions.0, -self.input_directions.1)
+                (
+                    -self.snakes[player].input_directions.0,
+                    -self.snakes[player].input_directions.1,
+                )
             } else {
-                (self.input_directions.0, self.input_directions.1)
+                (
+                    self.snakes[player].input_directions.0,
+                    self.snakes[player].input_directions.1,
+                )
             };
 
             // 判断输入方向是否合理(掉头跑)，否则强行修改成当前头部的方向
@@ -423,200 +809,701 @@ impl Game {
             // 当前头部坐标
             let current = self.get_index(head.0 as usize, head.1 as usize);
 
-            // 判断下次头部坐标是否撞墙，以及连续撞墙判定
-            if next_head.0 >= self.size as i32
-                || next_head.1 >= self.size as i32
+            // 判断下次头部坐标是否撞墙 (撞到障碍物也算撞墙)，以及连续撞墙判定
+            if next_head.0 >= self.rows as i32
+                || next_head.1 >= self.cols as i32
                 || next_head.0 < 0
                 || next_head.1 < 0
+                || self.cells[self.get_index(next_head.0 as usize, next_head.1 as usize)]
+                    .is_obstacle()
             {
-                return if ts - self.refreshes.last_knock_wall < 3_000 {
+                return if ts - self.snakes[player].last_knock_wall
+                    < self.config.double_wall_ms as i64
+                {
                     // 连续撞墙，game over
-                    false
+                    Err(GameOverReason::DoubleWallHit)
                 } else {
-                    if self.snake.timing_buff & 1 << DIZZINESS_BUFF == 0 {
-                        // 如果之前没有头晕buff，或者头晕 buff 过期了，撞墙后速度再变慢
-                        // 防止多次撞墙减速后速度成 0，然后上面出现 divide by zero
-                        self.snake.speed /= 3;
-                    }
-                    self.snake.timing_buff |= 1 << DIZZINESS_BUFF; // 加上头晕 buff
-                    self.refreshes.last_knock_wall = ts; // 更新撞墙时间
+                    self.emit(
+                        ts,
+                        GameEvent::WallKnocked {
+                            player,
+                            row: head.0,
+                            col: head.1,
+                        },
+                    );
+                    // 加上头晕 buff，已经头晕了就重新计时，速度不会再变慢
+                    let duration = self.config.dizziness_ms;
+                    self.add_effect(player, EffectKind::Dizziness, Some(duration), ts);
+                    self.snakes[player].score = self.snakes[player]
+                        .score
+                        .saturating_sub(score::WALL_KNOCK_PENALTY);
+                    self.snakes[player].last_knock_wall = ts; // 更新撞墙时间
                     self.cells[current].head_unhappy(); // 改成不开心表情
-                    self.snake.directions.pop_front(); // 删掉导致撞墙的方向(这个方向没有实际上使用到，所以要删掉，防止传播到尾部导致 bug)
-                    self.snake.directions.push_front(next_direction); // 补上下个输入方向当作当前头部的修正方向
-                    true
+                    self.touch(current);
+                    self.snakes[player].directions.pop_front(); // 删掉导致撞墙的方向(这个方向没有实际上使用到，所以要删掉，防止传播到尾部导致 bug)
+                    self.snakes[player].directions.push_front(next_direction); // 补上下个输入方向当作当前头部的修正方向
+                    Ok(None)
                 };
             }
 
             // 添上下一次头部的方向到队列中
-            self.snake.directions.push_front(next_direction);
-            // 更新头部坐标
-            self.snake.head = next_head;
-            // 将当前头部的地方改成身体 PS: 随机改变身体，可能会变成彩虹蛇(
-            self.cells[current].body();
-
-            // 下一个头部的坐标
-            let next = self.get_index(next_head.0 as usize, next_head.1 as usize);
-
-            if self.cells[next].is_body() {
-                // 吃到身体了
-                return false;
-            }
-
-            if self.cells[next].is_eatable() {
-                // 吃到了可以吃的东西
-
-                // 修改连续计数器
-                if self.cells[next].is_snack() {
-                    self.snake.snacks += 1;
-                    self.snake.goods = 0;
-                    self.snake.drugs = 0;
-                } else if self.cells[next].is_drug() {
-                    self.snake.drugs += 1;
-                    self.snake.goods = 0;
-                    self.snake.snacks = 0;
-                } else {
-                    self.snake.goods += 1;
-                    self.snake.hunger_cnt += 1;
-                    self.snake.snacks = 0;
-                    self.snake.drugs = 0;
-                }
+            self.snakes[player].directions.push_front(next_direction);
+            return Ok(Some(Step {
+                player,
+                next: next_head,
+                speed,
+                modifiers,
+            }));
+        }
+        Ok(None)
+    }
 
-                if self.snake.goods >= 4 {
-                    // 连续 4 次正向食物，清除 hunger buff
-                    self.clear_hunger_buff();
-                }
+    // 头部前进一格，原来的头部变成身体
+    fn advance_head(&mut self, step: &Step) {
+        let head = self.snakes[step.player].head;
+        let current = self.get_index(head.0 as usize, head.1 as usize);
+        // 更新头部坐标
+        self.snakes[step.player].head = step.next;
+        self.snakes[step.player].moves += 1;
+        // 将当前头部的地方改成身体 PS: 随机改变身体，可能会变成彩虹蛇(
+        self.cells[current].body(&mut self.rng);
+        self.touch(current);
+    }
 
-                // 计算速度翻倍
-                let mut speed_up = self.snake.goods / 6;
-                while speed_up > 0 {
-                    // (实际测试下来速度翻倍会变得很难)
-                    self.snake.speed *= 2;
-                    speed_up -= 1;
-                    self.snake.goods = 0;
-                }
+    // 吃掉下一个头部位置上的东西，返回是否变长 (变长时这一步尾巴不动)
+    fn eat(&mut self, step: &Step, ts: i64) -> Result<bool, GameOverReason> {
+        let (player, next_head) = (step.player, step.next);
+        let (speed, modifiers) = (step.speed, step.modifiers);
+        let next = self.get_index(next_head.0 as usize, next_head.1 as usize);
+        // 吃到了可以吃的东西
+        self.emit(
+            ts,
+            GameEvent::AteItem {
+                player,
+                cell: self.cells[next],
+                row: next_head.0,
+                col: next_head.1,
+            },
+        );
+        self.refreshes.items.retain(|item| item.pos != next);
+        self.snakes[player].eaten += 1;
+
+        // 修改连续计数器
+        if self.cells[next].is_snack() {
+            self.snakes[player].snacks += 1;
+            self.snakes[player].goods = 0;
+            self.snakes[player].drugs = 0;
+        } else if self.cells[next].is_drug() {
+            self.snakes[player].drugs += 1;
+            self.snakes[player].goods = 0;
+            self.snakes[player].snacks = 0;
+        } else {
+            self.snakes[player].goods += 1;
+            self.snakes[player].hunger_cnt += 1;
+            self.snakes[player].snacks = 0;
+            self.snakes[player].drugs = 0;
+        }
 
-                if self.snake.snacks >= 3 {
-                    // 连续吃 3 次零食，速度恢复正常
-                    self.snake.speed = 3;
-                }
+        // 按加速之前的速度计分
+        let streak = if self.cells[next].is_snack() {
+            self.snakes[player].snacks
+        } else if self.cells[next].is_drug() {
+            self.snakes[player].drugs as u32
+        } else {
+            self.snakes[player].goods
+        };
+        let points = score::item_points(self.cells[next], streak, speed, self.config.start_speed);
+        self.snakes[player].score = self.snakes[player].score.saturating_add(points);
+
+        if self.snakes[player].goods >= self.config.goods_to_clear_hunger {
+            // 连续 4 次正向食物，清除 hunger buff
+            self.remove_effect(player, EffectKind::Hungry, ts);
+        }
 
-                if self.snake.drugs >= 2 {
-                    // 连续吃药 game over;
-                    return false;
-                }
+        // 计算速度翻倍
+        let mut speed_up = self.snakes[player].goods / self.config.goods_to_double_speed;
+        while speed_up > 0 {
+            // (实际测试下来速度翻倍会变得很难)
+            self.snakes[player].speed = self.snakes[player].speed.saturating_mul(2);
+            speed_up -= 1;
+            self.snakes[player].goods = 0;
+            let speed = self.snakes[player].speed;
+            self.emit(ts, GameEvent::SpeedDoubled { player, speed });
+        }
 
-                // if 写开一点方便查看
-                if !self.cells[next].is_drug() {
-                    // 吃到的不是药品
-                    if self.snake.timing_buff & (1 << HUNGRY_BUFF) == 0 {
-                        // 没有饥饿 buff2
-                        if self.snake.snacks == 0 {
-                            // 吃到了正向食物
-                            // 这时需要变长
-                            self.snake.length += 1;
-                            self.refreshes.last_feed = ts;
-                            // 吃到了金苹果！！
-                            if self.cells[next] == Cell::FruitGoldenApple {
-                                self.cells[next].head_happy();
-                            } else {
-                                self.cells[next].head_normal();
-                            }
-                            return true;
-                        }
-                        // 吃了零食，少长一半！
-                        if self.snake.snacks % 2 != 0 {
-                            self.snake.length += 1;
-                            self.refreshes.last_feed = ts;
-                            self.cells[next].head_normal();
-                            return true;
-                        }
-                    }
-                    if self.snake.hunger_cnt >= 2 {
-                        // 有饥饿 buff2，但连续吃了两次正向食物
-                        // 这时需要变长
-                        self.snake.length += 1;
-                        self.snake.hunger_cnt = 0;
-                        self.refreshes.last_feed = ts;
+        if self.snakes[player].snacks >= self.config.snacks_to_reset_speed {
+            // 连续吃 3 次零食，速度恢复正常
+            self.snakes[player].speed = self.config.start_speed;
+            let speed = self.snakes[player].speed;
+            self.emit(ts, GameEvent::SpeedReset { player, speed });
+        }
+
+        if self.snakes[player].drugs >= self.config.drugs_to_die {
+            // 连续吃药 game over;
+            return Err(GameOverReason::DrugOverdose);
+        }
+
+        // 每种物品自己的效果
+        match self.cells[next] {
+            Cell::DrinkMilk => self.clear_effects(player, ts),
+            Cell::DrinkHoney => {
+                self.remove_effect(player, EffectKind::Hungry, ts);
+            }
+            Cell::SnackSpicyStrip => {
+                let duration = self.config.spicy_hunger_ms;
+                self.add_effect(player, EffectKind::Hungry, Some(duration), ts);
+            }
+            Cell::DrugLingeringHeal => self.spawn_heal_cloud(player, next_head, ts),
+            Cell::DrugSplashHeal => self.heal_around(next_head, ts),
+            Cell::DrugSplashHealthBoost => {
+                let duration = self.config.health_boost_ms;
+                self.add_effect(player, EffectKind::HealthBoost, Some(duration), ts);
+            }
+            _ => {}
+        }
+
+        // if 写开一点方便查看
+        if !self.cells[next].is_drug() {
+            // 吃到的不是药品
+            if modifiers.growth == Growth::Normal {
+                // 没有饥饿 buff2
+                if self.snakes[player].snacks == 0 {
+                    // 吃到了正向食物
+                    // 这时需要变长
+                    self.snakes[player].length += 1;
+                    let length = self.snakes[player].length;
+                    self.emit(ts, GameEvent::GrewLonger { player, length });
+                    self.snakes[player].last_feed = ts;
+                    // 吃到了金苹果！！
+                    if self.cells[next] == Cell::FruitGoldenApple {
+                        self.cells[next].head_happy();
+                    } else {
                         self.cells[next].head_normal();
-                        return true;
                     }
-                    // 饥饿 buff2 下吃到了零食不变长
-                    self.refreshes.last_feed = ts; // TODO: 是否该刷新 last_feed ?
-                } else {
-                    // 清除负面 buff
-                    self.clear_dizziness_buff();
-                    self.clear_hunger_buff();
-                    self.snake.snacks = 0;
+                    return Ok(true);
+                }
+                // 吃了零食，少长一半！
+                // (is_multiple_of 需要 Rust 1.87)
+                #[allow(clippy::manual_is_multiple_of)]
+                if self.snakes[player].snacks % 2 != 0 {
+                    self.snakes[player].length += 1;
+                    let length = self.snakes[player].length;
+                    self.emit(ts, GameEvent::GrewLonger { player, length });
+                    self.snakes[player].last_feed = ts;
+                    self.cells[next].head_normal();
+                    return Ok(true);
                 }
             }
+            if self.snakes[player].hunger_cnt >= 2 {
+                // 有饥饿 buff2，但连续吃了两次正向食物
+                // 这时需要变长
+                self.snakes[player].length += 1;
+                let length = self.snakes[player].length;
+                self.emit(ts, GameEvent::GrewLonger { player, length });
+                self.snakes[player].hunger_cnt = 0;
+                self.snakes[player].last_feed = ts;
+                self.cells[next].head_normal();
+                return Ok(true);
+            }
+            // 饥饿 buff2 下吃到了零食不变长
+            self.snakes[player].last_feed = ts; // TODO: 是否该刷新 last_feed ?
+        } else {
+            self.snakes[player].snacks = 0;
+        }
+        Ok(false)
+    }
 
-            // 将下一个头部方向的方块改成头部
-            self.cells[next].head_normal();
+    // 尾巴前进一格
+    fn move_tail(&mut self, player: usize) {
+        let tail = self.snakes[player].tail;
+        // 移除最后一个尾巴的方向
+        let tail_direction = self.snakes[player].directions.pop_back().unwrap();
+        // 下一个尾巴的坐标
+        let next_tail = self.wrap_position((tail.0 + tail_direction.0, tail.1 + tail_direction.1));
+        // 当前尾部坐标
+        let current = self.get_index(tail.0 as usize, tail.1 as usize);
+        // 移除尾巴
+        self.cells[current].blank();
+        self.owners[current] = NO_OWNER;
+        self.touch(current);
+        // 更新尾巴坐标
+        self.snakes[player].tail = next_tail;
+    }
 
-            let tail = self.snake.tail;
-            // 移除最后一个尾巴的方向
-            let tail_direction = self.snake.directions.pop_back().unwrap();
-            // 下一个尾巴的坐标
-            let next_tail = (tail.0 + tail_direction.0, tail.1 + tail_direction.1);
-            // 当前尾部坐标
-            let current = self.get_index(tail.0 as usize, tail.1 as usize);
-            // 移除尾巴
-            self.cells[current].blank();
-            // 更新尾巴坐标
-            self.snake.tail = next_tail;
+    // 所有蛇同时移动: 先决定每条蛇的下一个头部位置，再移动所有的尾巴，
+    // 最后一起判定碰撞，结果和玩家编号无关。自己的尾巴在移走之前判定，
+    // 和单人游戏一样走到自己的尾巴上算撞到自己
+    fn move_snakes(&mut self, alive: &[usize], refreshed: bool, ts: i64) {
+        let mut steps = Vec::new();
+        let mut knocked = Vec::new();
+        for &player in alive {
+            let result = if refreshed {
+                self.check_hunger(player, ts)
+            } else {
+                Ok(())
+            };
+            match result.and_then(|_| self.try_move(player, ts)) {
+                Ok(Some(step)) => steps.push(step),
+                Ok(None) => {}
+                Err(reason) => knocked.push((player, reason)),
+            }
+        }
+        // 饿死和连续撞墙与别的蛇无关，先出局
+        for (player, reason) in knocked.drain(..) {
+            self.knock_out(player, reason, ts);
+        }
+        if self.finished() {
+            return;
+        }
+
+        // 头对头: 走到同一个格子、互相交换位置，或者走到原地不动的蛇的头上
+        let mut results: Vec<Option<GameOverReason>> = vec![None; steps.len()];
+        for (i, step) in steps.iter().enumerate() {
+            let head = self.snakes[step.player].head;
+            for (j, other) in steps.iter().enumerate() {
+                if j != i
+                    && (other.next == step.next
+                        || (other.next == head && self.snakes[other.player].head == step.next))
+                {
+                    results[i] = Some(GameOverReason::HeadOn);
+                }
+            }
+            for (player, snake) in self.snakes.iter().enumerate() {
+                if snake.game_over.is_none()
+                    && snake.head == step.next
+                    && steps.iter().all(|other| other.player != player)
+                {
+                    results[i] = Some(GameOverReason::HeadOn);
+                    knocked.push((player, GameOverReason::HeadOn));
+                }
+            }
+        }
+
+        for step in &steps {
+            self.advance_head(step);
+        }
+
+        // 吃东西，吃到东西的格子不会撞到蛇
+        let mut ate = vec![false; steps.len()];
+        let mut grew = vec![false; steps.len()];
+        for (i, step) in steps.iter().enumerate() {
+            let next = self.get_index(step.next.0 as usize, step.next.1 as usize);
+            if results[i].is_none() && self.cells[next].is_eatable() {
+                ate[i] = true;
+                match self.eat(step, ts) {
+                    Ok(longer) => grew[i] = longer,
+                    Err(reason) => results[i] = Some(reason),
+                }
+            }
+        }
+
+        // 撞到自己的身体，和单人游戏一样，自己的尾巴还没有移走，也算撞到
+        for (i, step) in steps.iter().enumerate() {
+            let next = self.get_index(step.next.0 as usize, step.next.1 as usize);
+            if results[i].is_none()
+                && !ate[i]
+                && self.cells[next].is_body()
+                && self.owners[next] == step.player as u8
+            {
+                results[i] = Some(GameOverReason::SelfCollision);
+            }
+        }
+
+        // 没有变长的蛇移动尾巴，别的蛇空出来的格子这一帧就可以走
+        for (i, step) in steps.iter().enumerate() {
+            if results[i].is_none() && !grew[i] {
+                self.move_tail(step.player);
+            }
+        }
+
+        // 撞到别的蛇的身体 (别的蛇原来的头部这时也已经变成了身体)
+        for (i, step) in steps.iter().enumerate() {
+            let next = self.get_index(step.next.0 as usize, step.next.1 as usize);
+            if results[i].is_none() && !ate[i] && self.cells[next].is_body() {
+                results[i] = Some(GameOverReason::HitOtherSnake);
+            }
+        }
+
+        // 放上新的头部
+        for (i, step) in steps.iter().enumerate() {
+            if results[i].is_some() && !ate[i] {
+                continue;
+            }
+            let next = self.get_index(step.next.0 as usize, step.next.1 as usize);
+            self.owners[next] = step.player as u8;
+            if results[i].is_none() && !grew[i] {
+                // 将下一个头部方向的方块改成头部
+                self.cells[next].head_normal();
+            }
+            self.touch(next);
+        }
+
+        for (i, step) in steps.iter().enumerate() {
+            if let Some(reason) = results[i] {
+                knocked.push((step.player, reason));
+            }
+        }
+        knocked.sort_by_key(|&(player, _)| player);
+        for (player, reason) in knocked {
+            if self.snakes[player].game_over.is_none() {
+                self.knock_out(player, reason, ts);
+            }
         }
-        true
     }
 
     pub fn input(&mut self, d_row: i32, d_col: i32) {
-        self.input_directions.0 = d_row;
-        self.input_directions.1 = d_col;
+        self.input_for(0, d_row, d_col);
+    }
+
+    /// 多人游戏中第 `player` 条蛇的输入，不存在的玩家和上下左右以外的方向会被忽略
+    pub fn input_for(&mut self, player: usize, d_row: i32, d_col: i32) {
+        let direction = matches!((d_row, d_col), (0, 1) | (0, -1) | (1, 0) | (-1, 0));
+        if player >= self.snakes.len() || !direction {
+            return;
+        }
+        let ts = self.now();
+        self.record(ts, Action::Input(player, d_row, d_col));
+        let depth = self.config.input_queue_depth as usize;
+        let snake = &mut self.snakes[player];
+        let (l_row, l_col) = snake
+            .inputs
+            .back()
+            .copied()
+            .unwrap_or(snake.input_directions);
+        if (d_row, d_col) == (l_row, l_col) || (d_row, d_col) == (-l_row, -l_col) {
+            // 和上一个输入相同，或者是掉头 (反正会被 try_move 忽略)，不占队列
+            return;
+        }
+        if snake.inputs.len() >= depth {
+            // 队列满了，丢掉新的输入
+            return;
+        }
+        snake.inputs.push_back((d_row, d_col));
     }
 
     pub fn start(&mut self) {
+        let ts = self.now();
+        self.record(ts, Action::Start);
+        if matches!(self.status, Status::Pause) {
+            // 暂停期间的时间不算
+            self.last_clock = self.clock.now();
+        }
         self.status = Status::Start;
     }
 
     pub fn pause(&mut self) {
+        let ts = self.now();
+        self.record(ts, Action::Pause);
         self.status = Status::Pause;
     }
 
+    /// 按时钟推进游戏时间，一次最多前进 1s (`MAX_TICK_GAP_MS`)，
+    /// 需要一次走得更远的无头工具用 `tick_at`
     pub fn tick(&mut self) -> bool {
-        if matches!(self.status, Status::Pause) {
-            return true;
+        if self.finished() || matches!(self.status, Status::Pause) {
+            return !self.finished();
         }
         let _timer = Timer::new("Game::tick"); // profiler
-        let ts = Local::now().timestamp_millis();
-        self.check_buff(ts);
-        if !self.try_refresh_food(ts) {
+
+        // 两次 tick 间隔太久 (比如切到了别的标签页) 时游戏时间只前进 MAX_TICK_GAP_MS
+        let clock_now = self.clock.now();
+        let delta = (clock_now - self.last_clock).clamp(0, MAX_TICK_GAP_MS);
+        self.last_clock = clock_now;
+        self.tick_at(self.elapsed + delta)
+    }
+
+    /// 把游戏时间推进到 `ts` 并推进一帧，不读取时钟
+    ///
+    /// `ts` 是游戏时间 (ms，开局时为 0，暂停期间不走，见 `elapsed_ms()`)，
+    /// 不是时钟的读数。比 `elapsed_ms()` 小的 `ts` 当作 `elapsed_ms()`，游戏时间不会倒退
+    ///
+    /// 返回 false 表示游戏结束，结果见 `game_over()` 和 `victory()`
+    pub fn tick_at(&mut self, ts: i64) -> bool {
+        if self.finished() {
             return false;
         }
-        if !self.try_move(ts) {
-            return false;
+        if matches!(self.status, Status::Pause) {
+            return true;
         }
-        true
+        let ts = ts.max(self.elapsed);
+        self.elapsed = ts;
+        self.record(ts, Action::Tick);
+        let alive: Vec<usize> = (0..self.snakes.len())
+            .filter(|&player| self.snakes[player].game_over.is_none())
+            .collect();
+        for &player in &alive {
+            self.expire_effects(player, ts);
+        }
+        self.update_heal_clouds(ts);
+        self.expire_items(ts);
+        let refreshed = self.try_refresh_food(ts);
+        self.move_snakes(&alive, refreshed, ts);
+        self.check_victory(ts);
+        !self.finished()
+    }
+
+    // 按玩家编号检查胜利条件，第一条达成的蛇获胜
+    fn check_victory(&mut self, ts: i64) {
+        if self.game_over.is_some() {
+            return;
+        }
+        let free = if self.config.fill_board {
+            self.cells.iter().filter(|cell| !cell.is_obstacle()).count()
+        } else {
+            usize::MAX
+        };
+        for (player, snake) in self.snakes.iter().enumerate() {
+            if snake.game_over.is_some() {
+                continue;
+            }
+            let condition = if snake.length as usize >= free {
+                WinCondition::FillBoard
+            } else if self.config.target_length > 0 && snake.length >= self.config.target_length {
+                WinCondition::TargetLength
+            } else if self.config.target_score > 0 && snake.score >= self.config.target_score {
+                WinCondition::TargetScore
+            } else if self.config.survive_ms > 0
+                && ts - snake.spawned_at >= self.config.survive_ms as i64
+            {
+                WinCondition::SurviveDuration
+            } else {
+                continue;
+            };
+            let victory = Victory {
+                player,
+                condition,
+                ts,
+                stats: self.run_stats(player, ts),
+            };
+            self.victory = Some(victory);
+            self.emit(ts, GameEvent::Victory(victory));
+            return;
+        }
+    }
+
+    #[inline]
+    fn finished(&self) -> bool {
+        self.game_over.is_some() || self.victory.is_some()
+    }
+
+    // 第 player 条蛇到 ts 为止的统计
+    fn run_stats(&self, player: usize, ts: i64) -> RunStats {
+        let snake = &self.snakes[player];
+        RunStats {
+            duration_ms: ts - snake.spawned_at,
+            length: snake.length,
+            score: snake.score,
+            items_eaten: snake.eaten,
+            moves: snake.moves,
+        }
+    }
+
+    // 第 player 条蛇出局，所有蛇都出局时游戏结束
+    fn knock_out(&mut self, player: usize, reason: GameOverReason, ts: i64) {
+        let (row, col) = self.snakes[player].head;
+        let game_over = GameOver {
+            player,
+            reason,
+            ts,
+            row,
+            col,
+        };
+        self.snakes[player].game_over = Some(game_over);
+        self.emit(ts, GameEvent::GameOver(game_over));
+        if self.snakes.iter().all(|snake| snake.game_over.is_some()) {
+            self.game_over = Some(game_over);
+            return;
+        }
+        // 还有别的蛇在玩，把出局的蛇从棋盘上移走
+        for index in 0..self.cells.len() {
+            if self.owners[index] == player as u8 {
+                self.cells[index].blank();
+                self.owners[index] = NO_OWNER;
+                self.touch(index);
+            }
+        }
+    }
+
+    /// 取走上次调用之后发生的所有事件，前端每帧调用一次
+    pub fn take_events(&mut self) -> Vec<Event> {
+        self.events
+            .drain(..)
+            .map(|(ts, event)| Event::new(ts, event))
+            .collect()
+    }
+
+    /// 游戏时间 (ms)，暂停时不走，事件和结果中的时间都是游戏时间
+    pub fn elapsed_ms(&self) -> i64 {
+        self.elapsed
+    }
+
+    /// 游戏结束的原因、时间和位置，游戏还在进行时为空
+    /// 多人游戏在所有蛇都出局后结束，这里是最后出局的那条
+    pub fn game_over(&self) -> Option<GameOver> {
+        self.game_over
+    }
+
+    /// 获胜的蛇、胜利条件和当时的统计，没有人获胜时为空
+    pub fn victory(&self) -> Option<Victory> {
+        self.victory
     }
 
+    /// 按行存储的棋盘，共 rows * cols 个
     pub fn cells(&self) -> *const Cell {
         self.cells.as_ptr()
     }
 
+    /// (row, col) 上刷新出来的物品还能存在多久 (ms)，没有会过期的物品时为空
+    pub fn remaining_lifetime(&self, row: usize, col: usize) -> Option<u32> {
+        if row >= self.rows || col >= self.cols {
+            return None;
+        }
+        let pos = self.get_index(row, col);
+        let now = self.now();
+        self.refreshes
+            .items
+            .iter()
+            .find(|item| item.pos == pos)
+            .map(|item| (item.expires_at - now).clamp(0, u32::MAX as i64) as u32)
+    }
+
+    /// `within_ms` 之内会过期的物品的格子下标，前端可以让它们闪烁
+    pub fn expiring_items(&self, within_ms: u32) -> Vec<u32> {
+        let deadline = self.now() + within_ms as i64;
+        self.refreshes
+            .items
+            .iter()
+            .filter(|item| item.expires_at <= deadline)
+            .map(|item| item.pos as u32)
+            .collect()
+    }
+
+    /// 上次调用之后 cells 或 owners 变过的格子下标，前端只需要重画这些格子
+    /// 新建或读档后第一次调用返回整个棋盘
+    pub fn take_dirty(&mut self) -> Vec<u32> {
+        for &index in &self.dirty {
+            self.dirty_mark[index as usize] = false;
+        }
+        std::mem::take(&mut self.dirty)
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    /// 和 cells 一一对应，每个格子属于第几条蛇，不属于任何蛇的是 255 (`NO_OWNER`)
+    pub fn owners(&self) -> *const u8 {
+        self.owners.as_ptr()
+    }
+
+    pub fn players(&self) -> usize {
+        self.snakes.len()
+    }
+
+    /// 效果按 `EffectKind` 的值占一位，剩余时间等详细信息见 `active_effects`
     pub fn buffs(&self) -> u8 {
-        self.snake.timing_buff
+        self.player_buffs(0)
     }
 
     pub fn hungers(&self) -> u32 {
-        self.snake.hunger
+        self.player_hungers(0)
     }
 
     pub fn length(&self) -> u32 {
-        self.snake.length
+        self.player_length(0)
+    }
+
+    /// 当前得分，计分规则见 `score` 模块
+    pub fn score(&self) -> u32 {
+        self.player_score(0)
+    }
+
+    /// 存活时间、长度、得分等统计
+    pub fn stats(&self) -> RunStats {
+        self.player_stats(0).unwrap()
+    }
+
+    // 下面是多人游戏中每条蛇的状态，不存在的玩家返回 0
+
+    pub fn player_buffs(&self, player: usize) -> u8 {
+        self.snakes.get(player).map_or(0, |snake| snake.buff_bits())
+    }
+
+    pub fn player_hungers(&self, player: usize) -> u32 {
+        self.snakes.get(player).map_or(0, |snake| snake.hunger)
+    }
+
+    pub fn player_length(&self, player: usize) -> u32 {
+        self.snakes.get(player).map_or(0, |snake| snake.length)
+    }
+
+    pub fn player_score(&self, player: usize) -> u32 {
+        self.snakes.get(player).map_or(0, |snake| snake.score)
+    }
+
+    /// 第 `player` 条蛇出局的原因，还在玩时为空
+    pub fn player_game_over(&self, player: usize) -> Option<GameOver> {
+        self.snakes.get(player).and_then(|snake| snake.game_over)
+    }
+
+    /// 第 `player` 条蛇的统计，出局或游戏胜利后停在那一刻
+    pub fn player_stats(&self, player: usize) -> Option<RunStats> {
+        let snake = self.snakes.get(player)?;
+        let ts = match (snake.game_over, self.victory) {
+            (Some(game_over), _) => game_over.ts,
+            (None, Some(victory)) => victory.ts,
+            (None, None) => self.now(),
+        };
+        Some(self.run_stats(player, ts))
+    }
+
+    /// 到目前为止的录像，可以用 `Replay::to_bytes` 附在 bug 报告里
+    pub fn replay(&self) -> Replay {
+        match &self.recording {
+            Some(recording) => recording.clone(),
+            None => Replay::new(self.rows, self.cols, self.seed, self.now(), self.encode()),
+        }
+    }
+
+    /// 开启/关闭穿墙模式，只能在游戏开始前设置
+    pub fn set_wrap(&mut self, wrap: bool) -> bool {
+        if self.started() {
+            return false;
+        }
+        self.config.wrap = wrap;
+        true
+    }
+
+    pub fn wrap(&self) -> bool {
+        self.config.wrap
+    }
+
+    /// 在空白格子上放置障碍物 (基岩或黑曜石)，只能在游戏开始前布置
+    pub fn place_obstacle(&mut self, row: usize, col: usize, obstacle: Cell) -> bool {
+        if !obstacle.is_obstacle() || row >= self.rows || col >= self.cols {
+            return false;
+        }
+        if self.started() {
+            // 已经开始录像，再改动棋盘会导致录像无法复现
+            return false;
+        }
+        let index = self.get_index(row, col);
+        if self.cells[index] != Cell::Blank {
+            return false;
+        }
+        self.cells[index] = obstacle;
+        self.touch(index);
+        true
     }
 }
 
 impl Display for Game {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        for line in self.cells.as_slice().chunks(self.size) {
+        for line in self.cells.as_slice().chunks(self.cols) {
             for &cell in line {
                 write!(f, "{:3}", cell as u8)?;
             }
@@ -629,18 +1516,53 @@ impl Display for Game {
 
 #[cfg(test)]
 mod test {
+    use crate::clock::ManualClock;
     use crate::*;
 
+    // 测试用的游戏: 时钟和游戏时间从 0 开始，种子为 0，第一次刷新食物要等 food_refresh_ms
+    pub(crate) fn new_game(rows: usize, cols: usize) -> (Game, ManualClock) {
+        let clock = ManualClock::new(0);
+        let mut game = Game::new_with_clock(rows, cols, 0, Box::new(clock.clone()));
+        game.refreshes.last_eatable_refresh = 0;
+        (game, clock)
+    }
+
+    // 和 new_game 一样，但是还没有蛇，之后用 spawn_snake 放置
+    pub(crate) fn empty_game(rows: usize, cols: usize, config: GameConfig) -> (Game, ManualClock) {
+        let clock = ManualClock::new(0);
+        let mut game = Game::empty(rows, cols, config, 0, Box::new(clock.clone()));
+        game.refreshes.last_eatable_refresh = 0;
+        (game, clock)
+    }
+
+    // 在 (row, col) 上直接摆一个格子，不经过刷新
+    pub(crate) fn put(game: &mut Game, row: usize, col: usize, cell: Cell) {
+        let index = game.get_index(row, col);
+        game.cells[index] = cell;
+    }
+
+    // 12x12 的棋盘上只有一条在 (5, 3) 向右的蛇，测试期间不刷新食物
+    pub(crate) fn one_snake_game() -> (Game, ManualClock) {
+        let config = GameConfig {
+            food_refresh_ms: u32::MAX,
+            ..GameConfig::default()
+        };
+        let (mut game, clock) = empty_game(12, 12, config);
+        game.spawn_snake((5, 3), 2, (0, 1));
+        (game, clock)
+    }
+
     #[test]
     fn test_cell() {
+        let mut rng = GameRng::new(0);
         let mut cell = Cell::Blank;
-        cell.food();
+        cell.food(&mut rng);
         assert!(cell as u8 >= 19 && cell as u8 <= 23);
-        cell.drug();
+        cell.drug(&mut rng);
         assert!(cell as u8 >= 26 && cell as u8 <= 28);
         cell.fruit();
         assert!(matches!(cell, Cell::FruitGoldenApple));
-        cell.drink();
+        cell.drink(&mut rng);
         assert!(cell as u8 >= 24 && cell as u8 <= 25);
     }
 
@@ -649,4 +1571,679 @@ mod test {
         let game = Game::new_with_size(18);
         println!("{}", game);
     }
+
+    #[test]
+    fn test_manual_clock() {
+        let clock = ManualClock::new(0);
+        let mut game = Game::new_with_clock(18, 18, 1, Box::new(clock.clone()));
+        game.start();
+        clock.advance(300);
+        assert!(game.tick());
+        assert_eq!(game.snakes[0].head, (4, 3));
+        // 同一时刻再 tick 不会移动
+        assert!(game.tick());
+        assert_eq!(game.snakes[0].head, (4, 3));
+        assert!(game.tick_at(600));
+        assert_eq!(game.snakes[0].head, (4, 4));
+    }
+
+    #[test]
+    fn test_same_seed_same_game() {
+        let run = |seed| {
+            let clock = ManualClock::new(0);
+            let mut game = Game::new_with_clock(18, 18, seed, Box::new(clock.clone()));
+            game.start();
+            for _ in 0..60 {
+                clock.advance(100);
+                game.tick();
+            }
+            game.cells
+        };
+        assert_eq!(run(7), run(7));
+        assert_ne!(run(7), run(8));
+    }
+
+    #[test]
+    fn test_replay() {
+        let clock = ManualClock::new(1_000);
+        let mut game = Game::new_with_clock(18, 18, 3, Box::new(clock.clone()));
+        game.start();
+        let inputs = [(1, 0), (0, 1), (-1, 0), (0, 1), (1, 0)];
+        for i in 0..200 {
+            clock.advance(50);
+            if i % 40 == 0 {
+                let (d_row, d_col) = inputs[i / 40];
+                game.input(d_row, d_col);
+            }
+            if i == 120 {
+                game.pause();
+            }
+            if i == 130 {
+                game.start();
+            }
+            game.tick();
+        }
+
+        let replay = Replay::decode(&game.replay().to_bytes()).unwrap();
+        assert_eq!(replay, game.replay());
+        let replayed = replay.run().unwrap();
+        assert_eq!(replayed.cells, game.cells);
+        assert_eq!(replayed.snakes[0].head, game.snakes[0].head);
+        assert_eq!(replayed.snakes[0].length, game.snakes[0].length);
+        assert_eq!(replayed.replay(), replay);
+
+        // 录像有上限，满了以后不再记录
+        let mut replay = game.replay();
+        for _ in replay.len()..replay::MAX_RECORDS + 10 {
+            replay.record(0, Action::Tick);
+        }
+        assert_eq!(replay.len(), replay::MAX_RECORDS);
+        assert!(replay.is_full());
+    }
+
+    #[test]
+    fn test_rectangular_board() {
+        let clock = ManualClock::new(0);
+        let mut game = Game::new_with_clock(6, 20, 0, Box::new(clock.clone()));
+        assert_eq!(game.cells.len(), 6 * 20);
+        assert_eq!(game.to_string().lines().count(), 6);
+        game.start();
+        // 一直向右走，列数大于行数时不会提前撞墙
+        for _ in 0..17 {
+            clock.advance(300);
+            assert!(game.tick());
+        }
+        assert_eq!(game.snakes[0].head, (1, 19));
+        clock.advance(300);
+        assert!(game.tick());
+        assert_eq!(game.cells[game.get_index(1, 19)], Cell::HeadObserver);
+
+        // 太大的棋盘会被缩小，wasm 上 rows * cols 不会溢出
+        let game = Game::new_with_clock(usize::MAX, 20, 0, Box::new(clock.clone()));
+        assert_eq!((game.rows(), game.cols()), (MAX_BOARD_SIDE, 20));
+    }
+
+    #[test]
+    fn test_obstacle() {
+        let clock = ManualClock::new(0);
+        let mut game = Game::new_with_clock(10, 10, 0, Box::new(clock.clone()));
+        assert!(game.place_obstacle(2, 5, Cell::ObstacleBedrock));
+        assert!(!game.place_obstacle(2, 5, Cell::ObstacleObsidian));
+        assert!(!game.place_obstacle(2, 6, Cell::FoodBeef));
+        // 只留下蛇前面的路和最后一行的 5 个空格
+        for row in 0..10 {
+            for col in 0..10 {
+                let road = row == 2 && (1..=5).contains(&col);
+                if !road && (row != 9 || col < 5) {
+                    assert!(game.place_obstacle(row, col, Cell::ObstacleObsidian));
+                }
+            }
+        }
+        let obstacles = game.cells.clone();
+        game.start();
+        assert!(!game.place_obstacle(9, 9, Cell::ObstacleBedrock));
+        clock.advance(300);
+        assert!(game.tick());
+        // 食物不会刷在障碍物上
+        for (pos, cell) in game.cells.iter().enumerate() {
+            assert_eq!(obstacles[pos].is_obstacle(), cell.is_obstacle());
+        }
+        put(&mut game, 2, 4, Cell::Blank);
+
+        // 撞到障碍物: 头晕，3s 内再撞就死掉
+        clock.advance(300);
+        assert!(game.tick());
+        clock.advance(300);
+        assert!(game.tick());
+        assert_eq!(game.snakes[0].head, (2, 4));
+        assert_eq!(game.cells[game.get_index(2, 4)], Cell::HeadObserver);
+        assert!(game.buffs() & (1 << DIZZINESS_BUFF) > 0);
+        clock.advance(900);
+        assert!(!game.tick());
+        let game_over = game.game_over().unwrap();
+        assert_eq!(game_over.reason, GameOverReason::DoubleWallHit);
+        assert_eq!((game_over.ts, game_over.row, game_over.col), (1800, 2, 4));
+        // 结束之后不再推进
+        clock.advance(900);
+        assert!(!game.tick());
+        assert_eq!(game.game_over().unwrap().ts, 1800);
+    }
+
+    #[test]
+    fn test_wrap() {
+        let (mut game, clock) = new_game(4, 5);
+        assert!(game.set_wrap(true));
+        game.start();
+        assert!(!game.set_wrap(false));
+        // 从右边出去，左边进来，尾巴跟着穿过去
+        for col in [3, 4, 0, 1, 2] {
+            clock.advance(300);
+            assert!(game.tick());
+            assert_eq!(game.snakes[0].head, (1, col));
+        }
+        assert_eq!(game.snakes[0].tail, (1, 1));
+        assert_eq!(game.buffs(), 0);
+        // 输入的方向从下一步开始生效，向上穿过第 0 行
+        game.input(-1, 0);
+        for head in [(1, 3), (0, 3), (3, 3)] {
+            clock.advance(300);
+            assert!(game.tick());
+            assert_eq!(game.snakes[0].head, head);
+        }
+        assert_eq!(game.cells[game.get_index(3, 3)], Cell::HeadDispenser);
+        assert_eq!(game.cells.iter().filter(|c| c.is_body()).count(), 1);
+    }
+
+    #[test]
+    fn test_config() {
+        let config = GameConfig {
+            base_move_interval_ms: 100,
+            start_speed: 1,
+            starvation_ms: 500,
+            hungers_to_die: 1,
+            ..GameConfig::default()
+        };
+        let clock = ManualClock::new(0);
+        let mut game = Game::new_with_config_and_clock(18, 18, config, 0, Box::new(clock.clone()));
+        game.start();
+        clock.advance(150);
+        assert!(game.tick());
+        assert_eq!(game.snakes[0].head, (4, 3));
+        // 第一次刷新食物时就已经饿了 500ms 以上
+        game.refreshes.last_eatable_refresh = NEVER;
+        clock.advance(400);
+        assert!(!game.tick());
+        assert_eq!(game.game_over().unwrap().reason, GameOverReason::Starvation);
+    }
+
+    #[test]
+    fn test_game_over_reason() {
+        let (mut game, clock) = new_game(18, 18);
+        put(&mut game, 4, 3, Cell::DrugSplashHeal);
+        put(&mut game, 4, 4, Cell::DrugLingeringHeal);
+        game.start();
+        clock.advance(300);
+        assert!(game.tick());
+        assert!(game.game_over().is_none());
+        clock.advance(300);
+        assert!(!game.tick());
+        let game_over = game.game_over().unwrap();
+        assert_eq!(game_over.reason, GameOverReason::DrugOverdose);
+        assert_eq!((game_over.row, game_over.col), (4, 4));
+
+        // 掉头撞到自己，长度为 4 时 (10, 10) 是这一步要移走的尾巴，也算撞到
+        for length in [4, 5] {
+            let (mut game, clock) = empty_game(18, 18, GameConfig::default());
+            game.spawn_snake((10, 10), length, (0, 1));
+            game.start();
+            // 输入的方向从下一步开始生效，第 4 步撞到 (10, 10)
+            for input in [(1, 0), (0, -1), (-1, 0), (-1, 0)] {
+                game.input(input.0, input.1);
+                clock.advance(300);
+                game.tick();
+            }
+            assert_eq!(
+                game.game_over().unwrap().reason,
+                GameOverReason::SelfCollision
+            );
+        }
+    }
+
+    #[test]
+    fn test_events() {
+        use crate::event::{EventKind, GameEvent};
+
+        let (mut game, clock) = new_game(18, 18);
+        put(&mut game, 4, 3, Cell::DrugSplashHeal);
+        put(&mut game, 4, 4, Cell::DrugLingeringHeal);
+        game.start();
+        clock.advance(300);
+        assert!(game.tick());
+        assert_eq!(
+            game.drain_events(),
+            vec![(
+                300,
+                GameEvent::AteItem {
+                    player: 0,
+                    cell: Cell::DrugSplashHeal,
+                    row: 4,
+                    col: 3
+                }
+            )]
+        );
+        assert!(game.drain_events().is_empty());
+
+        clock.advance(300);
+        assert!(!game.tick());
+        let events = game.take_events();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].kind, EventKind::AteItem);
+        assert_eq!(events[0].cell, Cell::DrugLingeringHeal);
+        assert_eq!(events[1].kind, EventKind::GameOver);
+        assert_eq!(events[1].value, GameOverReason::DrugOverdose as u32);
+        assert_eq!((events[1].ts, events[1].row, events[1].col), (600, 4, 4));
+    }
+
+    #[test]
+    fn test_score() {
+        let (mut game, clock) = new_game(18, 18);
+        put(&mut game, 4, 3, Cell::FoodBeef);
+        put(&mut game, 4, 4, Cell::FruitGoldenApple);
+        game.start();
+        clock.advance(300);
+        game.tick();
+        assert_eq!(game.score(), score::FOOD_POINTS);
+        clock.advance(300);
+        game.tick();
+        // 第二次连击加 25%，再加上金苹果奖励
+        assert_eq!(
+            game.score(),
+            score::FOOD_POINTS + score::FRUIT_POINTS * 5 / 4 + score::GOLDEN_APPLE_BONUS
+        );
+
+        // 撞墙扣分
+        let before = game.score();
+        game.input(-1, 0);
+        while game.game_over().is_none() && game.score() == before {
+            clock.advance(300);
+            game.tick();
+        }
+        assert!(game.game_over().is_none());
+        assert_eq!(game.score(), before - score::WALL_KNOCK_PENALTY);
+    }
+
+    #[test]
+    fn test_multiplayer() {
+        let clock = ManualClock::new(0);
+        let game = Game::new_multiplayer_with_clock(
+            18,
+            18,
+            2,
+            GameConfig::default(),
+            0,
+            Box::new(clock.clone()),
+        );
+        assert_eq!(game.players(), 2);
+        assert_eq!(game.owners[game.get_index(4, 2)], 0);
+        assert_eq!(game.owners[game.get_index(13, 15)], 1);
+        assert_eq!(game.owners[game.get_index(0, 0)], NO_OWNER);
+        // 太小的棋盘会被放大到放得下所有的蛇
+        let game = Game::new_multiplayer_with_clock(
+            1,
+            1,
+            9,
+            GameConfig::default(),
+            0,
+            Box::new(clock.clone()),
+        );
+        assert_eq!((game.players(), game.rows(), game.cols()), (4, 2, 6));
+
+        // 头对头相撞，两条蛇都出局
+        let (mut game, clock) = empty_game(18, 18, GameConfig::default());
+        game.spawn_snake((5, 4), 2, (0, 1));
+        game.spawn_snake((5, 7), 2, (0, -1));
+        game.start();
+        clock.advance(300);
+        assert!(game.tick());
+        clock.advance(300);
+        assert!(!game.tick());
+        for player in 0..2 {
+            let game_over = game.player_game_over(player).unwrap();
+            assert_eq!(game_over.reason, GameOverReason::HeadOn);
+        }
+
+        // 撞到别的蛇的身体，只有撞上去的蛇出局
+        let (mut game, clock) = empty_game(18, 18, GameConfig::default());
+        game.spawn_snake((5, 6), 5, (0, 1));
+        game.spawn_snake((3, 4), 2, (1, 0));
+        game.start();
+        clock.advance(300);
+        assert!(game.tick());
+        clock.advance(300);
+        assert!(game.tick());
+        assert!(game.player_game_over(0).is_none());
+        assert_eq!(
+            game.player_game_over(1).unwrap().reason,
+            GameOverReason::HitOtherSnake
+        );
+        assert!(!game.owners.contains(&1));
+        assert_eq!(game.cells[game.get_index(4, 4)], Cell::Blank);
+        assert_eq!(game.owners[game.get_index(5, 8)], 0);
+        // 剩下的蛇继续移动
+        clock.advance(300);
+        assert!(game.tick());
+        assert_eq!(game.snakes[0].head, (5, 9));
+    }
+
+    #[test]
+    fn test_simultaneous_moves() {
+        type Spawn = ((usize, usize), u32, (i32, i32));
+        // 按顺序放下这些蛇，走两步，返回每条蛇出局的原因
+        let run = |snakes: &[Spawn]| -> Vec<Option<GameOverReason>> {
+            let (mut game, clock) = empty_game(18, 18, GameConfig::default());
+            for &(head, length, direction) in snakes {
+                game.spawn_snake(head, length, direction);
+            }
+            game.start();
+            for _ in 0..2 {
+                clock.advance(300);
+                game.tick();
+            }
+            (0..snakes.len())
+                .map(|player| {
+                    game.player_game_over(player)
+                        .map(|game_over| game_over.reason)
+                })
+                .collect()
+        };
+
+        // 走进别的蛇同一帧空出来的尾巴，不管谁是 0 号玩家都不会出局
+        let long: Spawn = ((5, 5), 3, (0, 1));
+        let short: Spawn = ((4, 3), 2, (1, 0));
+        assert_eq!(run(&[long, short]), [None, None]);
+        assert_eq!(run(&[short, long]), [None, None]);
+
+        // 走到同一个格子，两条蛇都出局
+        let left: Spawn = ((5, 4), 2, (0, 1));
+        let right: Spawn = ((5, 6), 2, (0, -1));
+        let head_on = Some(GameOverReason::HeadOn);
+        assert_eq!(run(&[left, right]), [head_on, head_on]);
+        assert_eq!(run(&[right, left]), [head_on, head_on]);
+
+        // 撞到别的蛇的身体，只有撞上去的蛇出局
+        let wall: Spawn = ((5, 6), 5, (0, 1));
+        let hit = Some(GameOverReason::HitOtherSnake);
+        let down: Spawn = ((3, 4), 2, (1, 0));
+        assert_eq!(run(&[wall, down]), [None, hit]);
+        assert_eq!(run(&[down, wall]), [hit, None]);
+    }
+
+    #[test]
+    fn test_input_queue() {
+        let (mut game, clock) = new_game(18, 18);
+
+        // 重复和掉头的输入不进队列
+        game.input(0, 1);
+        game.input(0, -1);
+        assert!(game.snakes[0].inputs.is_empty());
+
+        // 斜着走、原地不动和一次走两格的输入直接丢掉，也不录进录像
+        game.input(1, 1);
+        game.input(0, 0);
+        game.input(2, 0);
+        game.input(i32::MIN, 1);
+        assert!(game.snakes[0].inputs.is_empty());
+        assert_eq!(game.replay().len(), 2);
+        assert!(Game::restore(&game.save(), Box::new(clock.clone())).is_ok());
+
+        // 一步之内按上再按左，两个输入都不会丢
+        game.input(-1, 0);
+        game.input(0, -1);
+        game.start();
+        for head in [(4, 3), (3, 3), (3, 2)] {
+            clock.advance(300);
+            assert!(game.tick());
+            assert_eq!(game.snakes[0].head, head);
+        }
+
+        // 队列满了以后丢掉新的输入
+        game.config.input_queue_depth = 2;
+        game.input(1, 0);
+        game.input(0, 1);
+        game.input(-1, 0);
+        assert_eq!(game.snakes[0].inputs, [(1, 0), (0, 1)]);
+    }
+
+    #[test]
+    fn test_take_dirty() {
+        let (mut game, clock) = new_game(18, 18);
+        assert_eq!(game.take_dirty().len(), 18 * 18);
+        assert!(game.take_dirty().is_empty());
+        assert!(game.place_obstacle(4, 5, Cell::ObstacleBedrock));
+        assert_eq!(game.take_dirty(), [game.get_index(4, 5) as u32]);
+
+        // 移动一步: 新的头、原来的头变成身体、原来的尾巴
+        game.start();
+        clock.advance(300);
+        game.tick();
+        let mut dirty = game.take_dirty();
+        dirty.sort_unstable();
+        let expected: Vec<u32> = [(4, 1), (4, 2), (4, 3)]
+            .iter()
+            .map(|&(row, col)| game.get_index(row, col) as u32)
+            .collect();
+        assert_eq!(dirty, expected);
+
+        // 撞墙只改变头部的表情
+        clock.advance(300);
+        game.tick();
+        game.take_dirty();
+        clock.advance(300);
+        game.tick();
+        assert_eq!(game.cells[game.get_index(4, 4)], Cell::HeadObserver);
+        assert_eq!(game.take_dirty(), [game.get_index(4, 4) as u32]);
+    }
+
+    #[test]
+    fn test_item_lifetime() {
+        let (mut game, _) = new_game(18, 18);
+        let apple = game.get_index(10, 3);
+        let bread = game.get_index(10, 6);
+        // 物品种类是随机的，这里直接改掉
+        game.spawn_item(apple, 0);
+        game.cells[apple] = Cell::FruitGoldenApple;
+        game.refreshes.items[0].expires_at = 3000;
+        game.spawn_item(bread, 0);
+        game.cells[bread] = Cell::FoodBread;
+        game.refreshes.items[1].expires_at = 15000;
+        assert_eq!(game.remaining_lifetime(10, 3), Some(3000));
+        assert_eq!(game.expiring_items(3000), [apple as u32]);
+
+        game.start();
+        game.tick_at(3001);
+        assert_eq!(game.cells[apple], Cell::Blank);
+        assert_eq!(game.remaining_lifetime(10, 3), None);
+        assert_eq!(game.cells[bread], Cell::FoodBread);
+        assert_eq!(game.remaining_lifetime(10, 6), Some(11999));
+
+        // 刷新出来的物品按种类决定存在时间
+        game.tick_at(5001);
+        for item in &game.refreshes.items[1..] {
+            let lifetime = 5000 * game.cells[item.pos].lifetime_percent() as i64 / 100;
+            assert_eq!(item.expires_at - item.spawned_at, lifetime);
+        }
+    }
+
+    #[test]
+    fn test_victory() {
+        let config = GameConfig {
+            target_length: 3,
+            ..GameConfig::default()
+        };
+        let (mut game, clock) = empty_game(12, 12, config);
+        game.spawn_snake((5, 3), 2, (0, 1));
+        put(&mut game, 5, 4, Cell::FoodBeef);
+        game.start();
+        clock.advance(300);
+        assert!(!game.tick());
+        let victory = game.victory().unwrap();
+        assert_eq!(victory.condition, WinCondition::TargetLength);
+        assert_eq!(victory.ts, 300);
+        let stats = RunStats {
+            duration_ms: 300,
+            length: 3,
+            score: score::FOOD_POINTS,
+            items_eaten: 1,
+            moves: 1,
+        };
+        assert_eq!(victory.stats, stats);
+        assert!(game.game_over().is_none());
+        assert!(game
+            .drain_events()
+            .contains(&(300, GameEvent::Victory(victory))));
+        // 结束后统计不再变化
+        clock.advance(300);
+        assert!(!game.tick());
+        assert_eq!(game.stats(), stats);
+        let restored = Game::restore(&game.encode(), Box::new(clock.clone())).unwrap();
+        assert_eq!(restored.victory(), Some(victory));
+
+        let config = GameConfig {
+            survive_ms: 1000,
+            ..GameConfig::default()
+        };
+        let (mut game, clock) = empty_game(12, 12, config);
+        game.spawn_snake((5, 3), 2, (0, 1));
+        game.start();
+        clock.advance(900);
+        assert!(game.tick());
+        clock.advance(100);
+        assert!(!game.tick());
+        let victory = game.victory().unwrap();
+        assert_eq!(victory.condition, WinCondition::SurviveDuration);
+        assert_eq!(victory.stats.duration_ms, 1000);
+
+        // 1x4 的棋盘上有一个障碍物，吃一个食物就占满了
+        let config = GameConfig {
+            fill_board: true,
+            ..GameConfig::default()
+        };
+        let (mut game, clock) = empty_game(1, 4, config);
+        game.spawn_snake((0, 1), 2, (0, 1));
+        game.cells[2] = Cell::DrinkMilk;
+        game.cells[3] = Cell::ObstacleBedrock;
+        game.start();
+        clock.advance(300);
+        assert!(!game.tick());
+        assert_eq!(game.victory().unwrap().condition, WinCondition::FillBoard);
+    }
+
+    #[test]
+    fn test_pause() {
+        let clock = ManualClock::new(1_000_000);
+        let mut game = Game::new_with_clock(18, 18, 0, Box::new(clock.clone()));
+        game.start();
+        clock.advance(300);
+        assert!(game.tick());
+        assert_eq!(game.elapsed_ms(), 300);
+        // 暂停期间游戏时间不走
+        game.pause();
+        clock.advance(60_000);
+        assert!(game.tick());
+        assert_eq!(game.elapsed_ms(), 300);
+        game.start();
+        clock.advance(300);
+        assert!(game.tick());
+        assert_eq!(game.elapsed_ms(), 600);
+        // 很久没有 tick (切到了别的标签页) 也不会一下子饿死
+        clock.advance(60_000);
+        assert!(game.tick());
+        assert_eq!(game.elapsed_ms(), 600 + MAX_TICK_GAP_MS);
+        assert_eq!(game.snakes[0].hunger, 0);
+        assert_eq!(game.snakes[0].last_move, 600 + MAX_TICK_GAP_MS);
+        // 传入比游戏时间早的时间不会让游戏时间倒退
+        assert!(game.tick_at(0));
+        assert_eq!(game.elapsed_ms(), 600 + MAX_TICK_GAP_MS);
+    }
+
+    #[test]
+    fn test_item_effects() {
+        use crate::effect::EffectKind;
+
+        let (mut game, clock) = one_snake_game();
+        let items = [
+            Cell::SnackSpicyStrip,
+            Cell::DrinkHoney,
+            Cell::DrugSplashHealthBoost,
+            Cell::SnackSpicyStrip,
+            Cell::DrinkMilk,
+        ];
+        for (i, &cell) in items.iter().enumerate() {
+            put(&mut game, 5, 4 + i, cell);
+        }
+        let kinds = |game: &Game| -> Vec<EffectKind> {
+            game.effects(0).iter().map(|effect| effect.kind).collect()
+        };
+        let step = |game: &mut Game| {
+            clock.advance(300);
+            assert!(game.tick());
+        };
+        game.start();
+
+        // 辣条: 一段时间的饥饿
+        step(&mut game);
+        assert_eq!(kinds(&game), [EffectKind::Hungry]);
+        assert_eq!(game.effects(0)[0].duration_ms, Some(15_000));
+        // 蜂蜜: 治好饥饿
+        step(&mut game);
+        assert!(kinds(&game).is_empty());
+        // 生命提升: 保护期间不会饥饿
+        step(&mut game);
+        assert_eq!(kinds(&game), [EffectKind::HealthBoost]);
+        step(&mut game);
+        assert_eq!(kinds(&game), [EffectKind::HealthBoost]);
+        // 牛奶: 清除所有效果
+        step(&mut game);
+        assert!(kinds(&game).is_empty());
+    }
+
+    #[test]
+    fn test_heal_items() {
+        use crate::effect::EffectKind;
+        use crate::event::GameEvent;
+
+        let (mut game, clock) = one_snake_game();
+        game.spawn_snake((7, 2), 2, (0, 1));
+        game.spawn_snake((9, 2), 2, (0, 1));
+        put(&mut game, 5, 4, Cell::DrugSplashHeal);
+        put(&mut game, 5, 5, Cell::FoodBread);
+        put(&mut game, 5, 6, Cell::DrugLingeringHeal);
+        for player in 0..3 {
+            game.add_effect(player, EffectKind::Hungry, None, 0);
+        }
+        game.start();
+
+        // 喷溅治疗: 范围内的蛇都被治好
+        clock.advance(300);
+        assert!(game.tick());
+        assert!(game.effects(0).is_empty());
+        assert!(game.effects(1).is_empty());
+        assert_eq!(game.effects(2).len(), 1);
+
+        // 持续治疗: 留下 5x5 的治疗云，覆盖的格子都要重画
+        clock.advance(300);
+        game.tick();
+        game.take_dirty();
+        clock.advance(300);
+        game.tick();
+        let cloud = game.heal_cloud_cells();
+        assert_eq!(cloud.len(), 25);
+        let dirty = game.take_dirty();
+        assert!(cloud.iter().all(|index| dirty.contains(index)));
+        assert!(game.drain_events().contains(&(
+            900,
+            GameEvent::HealCloudSpawned {
+                player: 0,
+                row: 5,
+                col: 6
+            }
+        )));
+        let loaded = Game::restore(&game.save(), Box::new(clock.clone())).unwrap();
+        assert_eq!(loaded.heal_clouds(), game.heal_clouds());
+
+        // 云还在时继续治疗
+        game.add_effect(0, EffectKind::Hungry, None, 900);
+        clock.advance(300);
+        game.tick();
+        assert!(game.effects(0).is_empty());
+
+        // 云散了以后不再治疗
+        game.add_effect(0, EffectKind::Hungry, None, 1200);
+        game.take_dirty();
+        game.tick_at(6000);
+        assert!(game.heal_clouds().is_empty());
+        let dirty = game.take_dirty();
+        assert!(cloud.iter().all(|index| dirty.contains(index)));
+        assert_eq!(game.effects(0).len(), 1);
+    }
 }
@@ -0,0 +1,83 @@
+use wasm_bindgen::prelude::*;
+
+/// Why a run ended
+#[wasm_bindgen]
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GameOverReason {
+    SelfCollision = 0, // 吃到了自己的身体
+    Starvation = 1,    // 连续饥饿
+    DoubleWallHit = 2, // 短时间内连续撞墙
+    DrugOverdose = 3,  // 连续吃药
+    HitOtherSnake = 4, // 撞到了别的蛇的身体
+    HeadOn = 5,        // 和别的蛇头对头相撞，两条蛇都出局
+}
+
+impl GameOverReason {
+    pub(crate) fn from_u8(id: u8) -> Option<GameOverReason> {
+        Some(match id {
+            0 => GameOverReason::SelfCollision,
+            1 => GameOverReason::Starvation,
+            2 => GameOverReason::DoubleWallHit,
+            3 => GameOverReason::DrugOverdose,
+            4 => GameOverReason::HitOtherSnake,
+            5 => GameOverReason::HeadOn,
+            _ => return None,
+        })
+    }
+}
+
+/// How and where a snake's run ended, `(row, col)` is the head position at that moment
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GameOver {
+    pub player: usize,
+    pub reason: GameOverReason,
+    pub ts: i64,
+    pub row: i32,
+    pub col: i32,
+}
+
+/// Which configured goal a run was won by
+#[wasm_bindgen]
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WinCondition {
+    TargetLength = 0,    // 长度达到 target_length
+    SurviveDuration = 1, // 活过 survive_ms
+    FillBoard = 2,       // 蛇占满了所有不是障碍物的格子
+    TargetScore = 3,     // 得分达到 target_score
+}
+
+impl WinCondition {
+    pub(crate) fn from_u8(id: u8) -> Option<WinCondition> {
+        Some(match id {
+            0 => WinCondition::TargetLength,
+            1 => WinCondition::SurviveDuration,
+            2 => WinCondition::FillBoard,
+            3 => WinCondition::TargetScore,
+            _ => return None,
+        })
+    }
+}
+
+/// Summary of one snake's run
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RunStats {
+    pub duration_ms: i64, // 从出生到出局、胜利或现在
+    pub length: u32,
+    pub score: u32,
+    pub items_eaten: u32,
+    pub moves: u32, // 走了多少步，撞墙不算
+}
+
+/// Which snake won, how and when, with its statistics at that moment
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Victory {
+    pub player: usize,
+    pub condition: WinCondition,
+    pub ts: i64,
+    pub stats: RunStats,
+}
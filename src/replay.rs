@@ -0,0 +1,180 @@
+use crate::clock::ManualClock;
+use crate::codec::{DecodeError, Reader, Writer};
+use crate::snapshot::read_header;
+use crate::Game;
+use wasm_bindgen::prelude::*;
+
+const MAGIC: &[u8; 4] = b"SLGR";
+const VERSION: u8 = 1;
+
+/// 录像最多记录这么多次调用 (每秒 60 次 tick 大约 27 分钟)，之后的调用不再记录
+pub const MAX_RECORDS: usize = 100_000;
+
+/// A call made on a `Game` by the players (or the frontend loop)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    Input(usize, i32, i32), // (player, d_row, d_col)
+    Start,
+    Pause,
+    Tick,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Record {
+    pub ts: i64,
+    pub action: Action,
+}
+
+/// Everything needed to re-run a game: the game state at the first
+/// recorded call (so obstacles and other setup are included)
+/// and every call made on it since, with timestamps
+#[wasm_bindgen]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Replay {
+    rows: usize, // 这三项和 initial 里的一致，不写进录像
+    cols: usize,
+    seed: u64,
+    started_at: i64,
+    initial: Vec<u8>, // snapshot
+    records: Vec<Record>,
+}
+
+impl Replay {
+    pub(crate) fn new(
+        rows: usize,
+        cols: usize,
+        seed: u64,
+        started_at: i64,
+        initial: Vec<u8>,
+    ) -> Replay {
+        Replay {
+            rows,
+            cols,
+            seed,
+            started_at,
+            initial,
+            records: Vec::new(),
+        }
+    }
+
+    #[inline]
+    pub(crate) fn record(&mut self, ts: i64, action: Action) {
+        if self.records.len() < MAX_RECORDS {
+            self.records.push(Record { ts, action });
+        }
+    }
+
+    pub fn records(&self) -> &[Record] {
+        &self.records
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Replay, DecodeError> {
+        let (mut r, version) = Reader::new(bytes, MAGIC)?;
+        if version != VERSION {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+        let started_at = r.i64()?;
+        let initial = r.bytes()?.to_vec();
+        let (rows, cols, seed) = read_header(&initial)?;
+        let len = r.seq_len(9)?;
+        let mut records = Vec::with_capacity(len);
+        for _ in 0..len {
+            let tag = r.u8()?;
+            let ts = r.i64()?;
+            let action = match tag {
+                0 => Action::Tick,
+                1 => Action::Start,
+                2 => Action::Pause,
+                3 => Action::Input(r.u8()? as usize, r.i32()?, r.i32()?),
+                _ => return Err(DecodeError::Invalid("replay action")),
+            };
+            records.push(Record { ts, action });
+        }
+        r.finish()?;
+        Ok(Replay {
+            rows,
+            cols,
+            seed,
+            started_at,
+            initial,
+            records,
+        })
+    }
+
+    pub fn run(&self) -> Result<Game, DecodeError> {
+        let clock = ManualClock::new(self.started_at);
+        let mut game = Game::restore(&self.initial, Box::new(clock.clone()))?;
+        for record in &self.records {
+            clock.set(record.ts);
+            match record.action {
+                Action::Input(player, d_row, d_col) => game.input_for(player, d_row, d_col),
+                Action::Start => game.start(),
+                Action::Pause => game.pause(),
+                Action::Tick => {
+                    game.tick_at(record.ts);
+                }
+            }
+        }
+        Ok(game)
+    }
+}
+
+#[wasm_bindgen]
+impl Replay {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Replay, JsError> {
+        Ok(Replay::decode(bytes)?)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut w = Writer::new(MAGIC, VERSION);
+        w.i64(self.started_at);
+        w.bytes(&self.initial);
+        w.u32(self.records.len() as u32);
+        for record in &self.records {
+            let tag = match record.action {
+                Action::Tick => 0,
+                Action::Start => 1,
+                Action::Pause => 2,
+                Action::Input(..) => 3,
+            };
+            w.u8(tag);
+            w.i64(record.ts);
+            if let Action::Input(player, d_row, d_col) = record.action {
+                w.u8(player as u8);
+                w.i32(d_row);
+                w.i32(d_col);
+            }
+        }
+        w.finish()
+    }
+
+    /// 从初始状态重新执行录像中的每一次调用，返回最终状态的游戏
+    pub fn play(&self) -> Result<Game, JsError> {
+        Ok(self.run()?)
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    /// 录像已满，之后的调用没有被记录，重放只能重现到这里为止
+    pub fn is_full(&self) -> bool {
+        self.records.len() >= MAX_RECORDS
+    }
+}
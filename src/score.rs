@@ -0,0 +1,80 @@
+use crate::Cell;
+
+/// 各类物品的基础分
+pub const FOOD_POINTS: u32 = 10;
+pub const DRINK_POINTS: u32 = 15;
+pub const FRUIT_POINTS: u32 = 10;
+pub const SNACK_POINTS: u32 = 5;
+pub const DRUG_POINTS: u32 = 5;
+/// 金苹果的额外奖励，不受连击和速度加成
+pub const GOLDEN_APPLE_BONUS: u32 = 50;
+/// 每次撞墙扣的分，分数不会扣到 0 以下
+pub const WALL_KNOCK_PENALTY: u32 = 25;
+
+// 连击每多一次加 25%，最多加到 200%
+const STREAK_STEP_PERCENT: u32 = 25;
+const MAX_STREAK_PERCENT: u32 = 200;
+
+pub fn base_points(cell: Cell) -> u32 {
+    if cell.is_food() {
+        FOOD_POINTS
+    } else if cell.is_drink() {
+        DRINK_POINTS
+    } else if cell.is_fruit() {
+        FRUIT_POINTS
+    } else if cell.is_snack() {
+        SNACK_POINTS
+    } else if cell.is_drug() {
+        DRUG_POINTS
+    } else {
+        0
+    }
+}
+
+/// 吃到 `cell` 得到的分数
+///
+/// `streak` 是算上这一次后同类物品的连击数 (`goods`, `snacks` 或 `drugs`)，
+/// 速度加成是当前速度相对初始速度的倍数，头晕减速时得分也会变少
+pub fn item_points(cell: Cell, streak: u32, speed: u8, start_speed: u8) -> u32 {
+    let base = base_points(cell);
+    if base == 0 {
+        return 0;
+    }
+    let streak_percent = 100
+        + STREAK_STEP_PERCENT
+            * streak
+                .saturating_sub(1)
+                .min(MAX_STREAK_PERCENT / STREAK_STEP_PERCENT);
+    let points =
+        base as u64 * streak_percent as u64 / 100 * speed as u64 / start_speed.max(1) as u64;
+    let mut points = (points as u32).max(1);
+    if cell == Cell::FruitGoldenApple {
+        points += GOLDEN_APPLE_BONUS;
+    }
+    points
+}
+
+#[cfg(test)]
+mod test {
+    use crate::score::*;
+    use crate::Cell;
+
+    #[test]
+    fn test_item_points() {
+        assert_eq!(item_points(Cell::FoodBeef, 1, 3, 3), FOOD_POINTS);
+        assert_eq!(item_points(Cell::SnackCookie, 1, 3, 3), SNACK_POINTS);
+        assert_eq!(
+            item_points(Cell::FruitGoldenApple, 1, 3, 3),
+            FRUIT_POINTS + GOLDEN_APPLE_BONUS
+        );
+        // 连击加成
+        assert_eq!(item_points(Cell::FoodBeef, 3, 3, 3), 15);
+        assert_eq!(item_points(Cell::FoodBeef, 100, 3, 3), 30);
+        assert_eq!(item_points(Cell::FoodBeef, u32::MAX, 3, 3), 30);
+        // 速度加成
+        assert_eq!(item_points(Cell::DrinkMilk, 1, 6, 3), 30);
+        // 头晕减速后至少也有 1 分
+        assert_eq!(item_points(Cell::SnackCookie, 1, 1, 3), 1);
+        assert_eq!(item_points(Cell::Blank, 1, 3, 3), 0);
+    }
+}
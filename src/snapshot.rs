@@ -0,0 +1,639 @@
+use crate::clock::{Clock, WallClock};
+use crate::codec::{DecodeError, Reader, Writer};
+use crate::config::GameConfig;
+use crate::effect::{ActiveEffect, EffectKind, HealCloud};
+use crate::outcome::{GameOver, GameOverReason, RunStats, Victory, WinCondition};
+use crate::rng::GameRng;
+use crate::spawn::SpawnStrategyKind;
+use crate::{Cell, Game, Item, Refreshes, Snake, Status, MAX_PLAYERS, NEVER, NO_OWNER};
+use std::collections::VecDeque;
+use wasm_bindgen::prelude::*;
+
+const MAGIC: &[u8; 4] = b"SLGS";
+const VERSION: u8 = 1;
+
+fn write_config(w: &mut Writer, config: &GameConfig) {
+    w.u32(config.food_refresh_ms);
+    w.u32(config.starvation_ms);
+    w.u32(config.hungers_to_buff);
+    w.u32(config.hungers_to_die);
+    w.u32(config.dizziness_ms);
+    w.u32(config.double_wall_ms);
+    w.u32(config.base_move_interval_ms);
+    w.u8(config.start_speed);
+    w.u32(config.goods_to_clear_hunger);
+    w.u32(config.goods_to_double_speed);
+    w.u32(config.snacks_to_reset_speed);
+    w.u8(config.drugs_to_die);
+    w.bool(config.wrap);
+    w.u8(config.input_queue_depth);
+    w.u8(config.spawn_strategy as u8);
+    w.u32(config.target_length);
+    w.u32(config.survive_ms);
+    w.bool(config.fill_board);
+    w.u32(config.target_score);
+    w.u32(config.spicy_hunger_ms);
+    w.u8(config.heal_radius);
+    w.u32(config.heal_cloud_ms);
+    w.u32(config.health_boost_ms);
+}
+
+fn read_config(r: &mut Reader<'_>) -> Result<GameConfig, DecodeError> {
+    let config = GameConfig {
+        food_refresh_ms: r.u32()?,
+        starvation_ms: r.u32()?,
+        hungers_to_buff: r.u32()?,
+        hungers_to_die: r.u32()?,
+        dizziness_ms: r.u32()?,
+        double_wall_ms: r.u32()?,
+        base_move_interval_ms: r.u32()?,
+        start_speed: r.u8()?,
+        goods_to_clear_hunger: r.u32()?,
+        goods_to_double_speed: r.u32()?,
+        snacks_to_reset_speed: r.u32()?,
+        drugs_to_die: r.u8()?,
+        wrap: r.bool()?,
+        input_queue_depth: r.u8()?,
+        spawn_strategy: SpawnStrategyKind::from_u8(r.u8()?)
+            .ok_or(DecodeError::Invalid("spawn strategy"))?,
+        target_length: r.u32()?,
+        survive_ms: r.u32()?,
+        fill_board: r.bool()?,
+        target_score: r.u32()?,
+        spicy_hunger_ms: r.u32()?,
+        heal_radius: r.u8()?,
+        heal_cloud_ms: r.u32()?,
+        health_boost_ms: r.u32()?,
+    };
+    if !config.is_valid() {
+        return Err(DecodeError::Invalid("config"));
+    }
+    Ok(config)
+}
+
+fn write_game_over(w: &mut Writer, game_over: &Option<GameOver>) {
+    w.bool(game_over.is_some());
+    if let Some(game_over) = game_over {
+        w.u8(game_over.player as u8);
+        w.u8(game_over.reason as u8);
+        w.i64(game_over.ts);
+        w.i32(game_over.row);
+        w.i32(game_over.col);
+    }
+}
+
+fn read_game_over(r: &mut Reader<'_>, players: usize) -> Result<Option<GameOver>, DecodeError> {
+    if !r.bool()? {
+        return Ok(None);
+    }
+    let player = r.u8()? as usize;
+    if player >= players {
+        return Err(DecodeError::Invalid("player"));
+    }
+    let reason = GameOverReason::from_u8(r.u8()?).ok_or(DecodeError::Invalid("reason"))?;
+    Ok(Some(GameOver {
+        player,
+        reason,
+        ts: r.i64()?,
+        row: r.i32()?,
+        col: r.i32()?,
+    }))
+}
+
+fn write_effects(w: &mut Writer, effects: &[ActiveEffect]) {
+    w.u32(effects.len() as u32);
+    for effect in effects {
+        w.u8(effect.kind as u8);
+        w.i64(effect.started_at);
+        w.bool(effect.duration_ms.is_some());
+        w.u32(effect.duration_ms.unwrap_or(0));
+        w.u8(effect.stacks);
+    }
+}
+
+fn read_effects(r: &mut Reader<'_>, elapsed: i64) -> Result<Vec<ActiveEffect>, DecodeError> {
+    let len = r.seq_len(15)?;
+    let mut effects: Vec<ActiveEffect> = Vec::with_capacity(len);
+    for _ in 0..len {
+        let kind = EffectKind::from_u8(r.u8()?).ok_or(DecodeError::Invalid("effect"))?;
+        let started_at = read_time(r, elapsed)?;
+        let duration_ms = if r.bool()? {
+            Some(r.u32()?)
+        } else {
+            r.u32()?;
+            None
+        };
+        let stacks = r.u8()?;
+        if stacks == 0
+            || stacks > kind.def().max_stacks
+            || effects.iter().any(|effect| effect.kind == kind)
+        {
+            return Err(DecodeError::Invalid("effect"));
+        }
+        effects.push(ActiveEffect {
+            kind,
+            started_at,
+            duration_ms,
+            stacks,
+        });
+    }
+    Ok(effects)
+}
+
+// 过去某件事发生的时间，在 NEVER 和存档时的游戏时间之间，
+// 否则 tick 时相减会溢出，或者算出负的间隔
+fn read_time(r: &mut Reader<'_>, elapsed: i64) -> Result<i64, DecodeError> {
+    let ts = r.i64()?;
+    if !(NEVER..=elapsed).contains(&ts) {
+        return Err(DecodeError::Invalid("time"));
+    }
+    Ok(ts)
+}
+
+// 方向必须是上下左右之一
+fn read_direction(r: &mut Reader<'_>) -> Result<(i32, i32), DecodeError> {
+    let direction = (r.i32()?, r.i32()?);
+    match direction {
+        (0, 1) | (0, -1) | (1, 0) | (-1, 0) => Ok(direction),
+        _ => Err(DecodeError::Invalid("direction")),
+    }
+}
+
+struct Board<'a> {
+    rows: usize,
+    cols: usize,
+    wrap: bool,
+    cells: &'a [Cell],
+    owners: &'a [u8],
+}
+
+// 从尾巴沿着 directions 走到头部，每一节都要在棋盘上并且属于这条蛇，
+// 否则之后移动尾巴时会越界
+fn check_body(board: &Board<'_>, snake: &Snake, player: usize) -> Result<(), DecodeError> {
+    if snake.directions.len() != snake.length as usize {
+        return Err(DecodeError::Invalid("length"));
+    }
+    let (rows, cols) = (board.rows as i32, board.cols as i32);
+    let owned = |(row, col): (i32, i32)| {
+        let index = row as usize * board.cols + col as usize;
+        board.owners[index] == player as u8
+            && (board.cells[index].is_body() || board.cells[index].is_head())
+    };
+    let mut pos = snake.tail;
+    for &(d_row, d_col) in snake.directions.iter().skip(1).rev() {
+        if !owned(pos) {
+            return Err(DecodeError::Invalid("body"));
+        }
+        pos = (pos.0 + d_row, pos.1 + d_col);
+        if board.wrap {
+            pos = (pos.0.rem_euclid(rows), pos.1.rem_euclid(cols));
+        } else if pos.0 < 0 || pos.1 < 0 || pos.0 >= rows || pos.1 >= cols {
+            return Err(DecodeError::Invalid("body"));
+        }
+    }
+    if pos != snake.head || !owned(pos) {
+        return Err(DecodeError::Invalid("body"));
+    }
+    Ok(())
+}
+
+fn write_victory(w: &mut Writer, victory: &Option<Victory>) {
+    w.bool(victory.is_some());
+    if let Some(victory) = victory {
+        w.u8(victory.player as u8);
+        w.u8(victory.condition as u8);
+        w.i64(victory.ts);
+        w.i64(victory.stats.duration_ms);
+        w.u32(victory.stats.length);
+        w.u32(victory.stats.score);
+        w.u32(victory.stats.items_eaten);
+        w.u32(victory.stats.moves);
+    }
+}
+
+fn read_victory(r: &mut Reader<'_>, players: usize) -> Result<Option<Victory>, DecodeError> {
+    if !r.bool()? {
+        return Ok(None);
+    }
+    let player = r.u8()? as usize;
+    if player >= players {
+        return Err(DecodeError::Invalid("player"));
+    }
+    let condition = WinCondition::from_u8(r.u8()?).ok_or(DecodeError::Invalid("condition"))?;
+    Ok(Some(Victory {
+        player,
+        condition,
+        ts: r.i64()?,
+        stats: RunStats {
+            duration_ms: r.i64()?,
+            length: r.u32()?,
+            score: r.u32()?,
+            items_eaten: r.u32()?,
+            moves: r.u32()?,
+        },
+    }))
+}
+
+// 存档开头的行数、列数和种子，录像从初始状态里取，不另外保存
+pub(crate) fn read_header(bytes: &[u8]) -> Result<(usize, usize, u64), DecodeError> {
+    let (mut r, version) = Reader::new(bytes, MAGIC)?;
+    if version != VERSION {
+        return Err(DecodeError::UnsupportedVersion(version));
+    }
+    let rows = r.u32()? as usize;
+    let cols = r.u32()? as usize;
+    read_config(&mut r)?;
+    Ok((rows, cols, r.u64()?))
+}
+
+impl Game {
+    /// 从存档恢复游戏，存档时的时间线会接到 `clock` 上继续走
+    pub fn restore(bytes: &[u8], clock: Box<dyn Clock>) -> Result<Game, DecodeError> {
+        let (mut r, version) = Reader::new(bytes, MAGIC)?;
+        if version != VERSION {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+        let rows = r.u32()? as usize;
+        let cols = r.u32()? as usize;
+        let config = read_config(&mut r)?;
+        let seed = r.u64()?;
+        let rng = GameRng::new(r.u64()?);
+        let elapsed = r.i64()?;
+        if !(0..=-NEVER).contains(&elapsed) {
+            return Err(DecodeError::Invalid("elapsed"));
+        }
+        let status = match r.u8()? {
+            0 => Status::Pause,
+            1 => Status::Start,
+            _ => return Err(DecodeError::Invalid("status")),
+        };
+
+        let cells = r
+            .bytes()?
+            .iter()
+            .map(|&id| Cell::from_u8(id).ok_or(DecodeError::Invalid("cell")))
+            .collect::<Result<Vec<_>, _>>()?;
+        if rows == 0 || cols == 0 || rows.checked_mul(cols) != Some(cells.len()) {
+            return Err(DecodeError::Invalid("board size"));
+        }
+        let in_board = |(row, col): (i32, i32)| {
+            if row < 0 || col < 0 || row as usize >= rows || col as usize >= cols {
+                Err(DecodeError::Invalid("position"))
+            } else {
+                Ok((row, col))
+            }
+        };
+
+        let last_eatable_refresh = read_time(&mut r, elapsed)?;
+        let len = r.seq_len(20)?;
+        let mut items = Vec::with_capacity(len);
+        for _ in 0..len {
+            let pos = r.u32()? as usize;
+            if pos >= cells.len() {
+                return Err(DecodeError::Invalid("position"));
+            }
+            let spawned_at = read_time(&mut r, elapsed)?;
+            let expires_at = r.i64()?;
+            if expires_at < spawned_at {
+                return Err(DecodeError::Invalid("time"));
+            }
+            items.push(Item {
+                pos,
+                spawned_at,
+                expires_at,
+            });
+        }
+        let refreshes = Refreshes {
+            last_eatable_refresh,
+            items,
+        };
+        let len = r.seq_len(16)?;
+        let mut heal_clouds = Vec::with_capacity(len);
+        for _ in 0..len {
+            let (row, col) = in_board((r.i32()?, r.i32()?))?;
+            // 治疗云在过去某个时间放下，最多持续 heal_cloud_ms
+            let expires_at = r.i64()?;
+            if !(NEVER..=elapsed + config.heal_cloud_ms as i64).contains(&expires_at) {
+                return Err(DecodeError::Invalid("time"));
+            }
+            heal_clouds.push(HealCloud {
+                row,
+                col,
+                expires_at,
+            });
+        }
+
+        let players = r.u8()? as usize;
+        if players == 0 || players > MAX_PLAYERS {
+            return Err(DecodeError::Invalid("players"));
+        }
+        let owners = r.bytes()?.to_vec();
+        if owners.len() != cells.len()
+            || owners
+                .iter()
+                .any(|&owner| owner != NO_OWNER && owner as usize >= players)
+        {
+            return Err(DecodeError::Invalid("owners"));
+        }
+        let mut snakes = Vec::with_capacity(players);
+        for player in 0..players {
+            let input_directions = read_direction(&mut r)?;
+            let len = r.seq_len(8)?;
+            let mut inputs = VecDeque::with_capacity(len);
+            for _ in 0..len {
+                inputs.push_back(read_direction(&mut r)?);
+            }
+            let head = in_board((r.i32()?, r.i32()?))?;
+            let len = r.seq_len(8)?;
+            let mut directions = VecDeque::with_capacity(len);
+            for _ in 0..len {
+                directions.push_back(read_direction(&mut r)?);
+            }
+            if directions.is_empty() {
+                return Err(DecodeError::Invalid("directions"));
+            }
+            let snake = Snake {
+                input_directions,
+                inputs,
+                head,
+                directions,
+                tail: in_board((r.i32()?, r.i32()?))?,
+                goods: r.u32()?,
+                snacks: r.u32()?,
+                drugs: r.u8()?,
+                hunger: r.u32()?,
+                hunger_cnt: r.u8()?,
+                speed: r.u8()?,
+                effects: read_effects(&mut r, elapsed)?,
+                length: r.u32()?,
+                score: r.u32()?,
+                last_feed: read_time(&mut r, elapsed)?,
+                last_knock_wall: read_time(&mut r, elapsed)?,
+                last_move: read_time(&mut r, elapsed)?,
+                spawned_at: read_time(&mut r, elapsed)?,
+                eaten: r.u32()?,
+                moves: r.u32()?,
+                game_over: read_game_over(&mut r, players)?,
+            };
+            if snake.speed == 0 {
+                return Err(DecodeError::Invalid("speed"));
+            }
+            if snake.game_over.is_none() {
+                let board = Board {
+                    rows,
+                    cols,
+                    wrap: config.wrap,
+                    cells: &cells,
+                    owners: &owners,
+                };
+                check_body(&board, &snake, player)?;
+            }
+            snakes.push(snake);
+        }
+
+        let game_over = read_game_over(&mut r, players)?;
+        let victory = read_victory(&mut r, players)?;
+        r.finish()?;
+
+        Ok(Game {
+            rows,
+            cols,
+            config,
+            spawner: config.spawn_strategy.strategy(),
+            snakes,
+            refreshes,
+            heal_clouds,
+            status,
+            cells,
+            owners,
+            elapsed,
+            last_clock: clock.now(),
+            clock,
+            seed,
+            rng,
+            // 读档后的调用录在一份新的录像里，从读档时的状态开始
+            recording: None,
+            game_over,
+            victory,
+            events: VecDeque::new(),
+            dirty: (0..(rows * cols) as u32).collect(),
+            dirty_mark: vec![true; rows * cols],
+        })
+    }
+
+    /// 编码当前状态，计时器都是游戏时间，读档时不需要换算
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut w = Writer::new(MAGIC, VERSION);
+        w.u32(self.rows as u32);
+        w.u32(self.cols as u32);
+        write_config(&mut w, &self.config);
+        w.u64(self.seed);
+        w.u64(self.rng.state());
+        w.i64(self.elapsed);
+        w.u8(match self.status {
+            Status::Pause => 0,
+            Status::Start => 1,
+        });
+        w.bytes(
+            &self
+                .cells
+                .iter()
+                .map(|&cell| cell as u8)
+                .collect::<Vec<_>>(),
+        );
+
+        let refreshes = &self.refreshes;
+        w.i64(refreshes.last_eatable_refresh);
+        w.u32(refreshes.items.len() as u32);
+        for item in &refreshes.items {
+            w.u32(item.pos as u32);
+            w.i64(item.spawned_at);
+            w.i64(item.expires_at);
+        }
+        w.u32(self.heal_clouds.len() as u32);
+        for cloud in &self.heal_clouds {
+            w.i32(cloud.row);
+            w.i32(cloud.col);
+            w.i64(cloud.expires_at);
+        }
+
+        w.u8(self.snakes.len() as u8);
+        w.bytes(&self.owners);
+        for snake in &self.snakes {
+            w.i32(snake.input_directions.0);
+            w.i32(snake.input_directions.1);
+            w.u32(snake.inputs.len() as u32);
+            for &(d_row, d_col) in &snake.inputs {
+                w.i32(d_row);
+                w.i32(d_col);
+            }
+            w.i32(snake.head.0);
+            w.i32(snake.head.1);
+            w.u32(snake.directions.len() as u32);
+            for &(d_row, d_col) in &snake.directions {
+                w.i32(d_row);
+                w.i32(d_col);
+            }
+            w.i32(snake.tail.0);
+            w.i32(snake.tail.1);
+            w.u32(snake.goods);
+            w.u32(snake.snacks);
+            w.u8(snake.drugs);
+            w.u32(snake.hunger);
+            w.u8(snake.hunger_cnt);
+            w.u8(snake.speed);
+            write_effects(&mut w, &snake.effects);
+            w.u32(snake.length);
+            w.u32(snake.score);
+            w.i64(snake.last_feed);
+            w.i64(snake.last_knock_wall);
+            w.i64(snake.last_move);
+            w.i64(snake.spawned_at);
+            w.u32(snake.eaten);
+            w.u32(snake.moves);
+            write_game_over(&mut w, &snake.game_over);
+        }
+
+        write_game_over(&mut w, &self.game_over);
+        write_victory(&mut w, &self.victory);
+        w.finish()
+    }
+}
+
+#[wasm_bindgen]
+impl Game {
+    /// 存档，包含棋盘、蛇的全部状态和计时器，不包含录像
+    pub fn save(&self) -> Vec<u8> {
+        self.encode()
+    }
+
+    /// 读档，供前端从 localStorage 恢复暂停的游戏
+    pub fn load(bytes: &[u8]) -> Result<Game, JsError> {
+        Ok(Game::restore(bytes, Box::new(WallClock))?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::clock::ManualClock;
+    use crate::codec::DecodeError;
+    use crate::effect::EffectKind;
+    use crate::{Game, NO_OWNER};
+
+    #[test]
+    fn test_save_and_load() {
+        let clock = ManualClock::new(10_000);
+        let mut game = Game::new_with_clock(12, 30, 5, Box::new(clock.clone()));
+        game.start();
+        for _ in 0..40 {
+            clock.advance(100);
+            game.tick();
+            assert!(Game::restore(&game.save(), Box::new(clock.clone())).is_ok());
+        }
+        game.input(1, 0);
+        let bytes = game.save();
+
+        // 一小时后在另一个时钟上恢复
+        let other = ManualClock::new(3_600_000);
+        let mut loaded = Game::restore(&bytes, Box::new(other.clone())).unwrap();
+        assert_eq!(loaded.save(), bytes);
+        for _ in 0..40 {
+            clock.advance(100);
+            other.advance(100);
+            assert_eq!(game.tick(), loaded.tick());
+        }
+        assert_eq!(loaded.cells, game.cells);
+        assert_eq!(loaded.snakes[0].head, game.snakes[0].head);
+        assert_eq!(loaded.snakes[0].directions, game.snakes[0].directions);
+        // 存档里没有录像，读档后从读档时的状态重新开始录
+        assert!(bytes.len() < game.replay().to_bytes().len());
+        assert_eq!(loaded.replay().run().unwrap().cells, loaded.cells);
+    }
+
+    #[test]
+    fn test_load_corrupted() {
+        let game = Game::new_with_clock(8, 8, 0, Box::new(ManualClock::new(0)));
+        let bytes = game.save();
+        let clock = || Box::new(ManualClock::new(0));
+        assert_eq!(
+            Game::restore(&bytes[..bytes.len() - 1], clock()).err(),
+            Some(DecodeError::Truncated)
+        );
+        assert_eq!(
+            Game::restore(b"nope", clock()).err(),
+            Some(DecodeError::BadMagic)
+        );
+        let mut bad = bytes.clone();
+        bad[4] = 99;
+        assert_eq!(
+            Game::restore(&bad, clock()).err(),
+            Some(DecodeError::UnsupportedVersion(99))
+        );
+
+        // 能读进来但是之后 tick 会出错的数据
+        let corrupt = |edit: fn(&mut Game)| {
+            let mut game = Game::new_with_clock(8, 8, 0, clock());
+            edit(&mut game);
+            Game::restore(&game.encode(), clock()).err()
+        };
+        let bad_direction = Some(DecodeError::Invalid("direction"));
+        let bad_body = Some(DecodeError::Invalid("body"));
+        assert_eq!(
+            corrupt(|game| game.snakes[0].input_directions = (0, 0)),
+            bad_direction
+        );
+        assert_eq!(
+            corrupt(|game| game.snakes[0].inputs.push_back((1, 1))),
+            bad_direction
+        );
+        assert_eq!(
+            corrupt(|game| game.snakes[0].directions[1] = (0, 2)),
+            bad_direction
+        );
+        assert_eq!(
+            corrupt(|game| game.snakes[0].length += 1),
+            Some(DecodeError::Invalid("length"))
+        );
+        assert_eq!(
+            corrupt(|game| game.snakes[0].directions[1] = (1, 0)),
+            bad_body
+        );
+        assert_eq!(corrupt(|game| game.snakes[0].tail = (0, 0)), bad_body);
+        assert_eq!(
+            corrupt(|game| {
+                let (row, col) = game.snakes[0].head;
+                let head = game.get_index(row as usize, col as usize);
+                game.owners[head] = NO_OWNER;
+            }),
+            bad_body
+        );
+
+        // 晚于存档时游戏时间的计时器，tick 时会算出负的间隔
+        let bad_time = Some(DecodeError::Invalid("time"));
+        assert_eq!(
+            corrupt(|game| game.elapsed = -5000),
+            Some(DecodeError::Invalid("elapsed"))
+        );
+        assert_eq!(
+            corrupt(|game| game.refreshes.last_eatable_refresh = 1),
+            bad_time
+        );
+        assert_eq!(corrupt(|game| game.snakes[0].last_move = 1), bad_time);
+        assert_eq!(
+            corrupt(|game| game.snakes[0].last_knock_wall = i64::MIN),
+            bad_time
+        );
+        assert_eq!(
+            corrupt(|game| game.add_effect(0, EffectKind::Dizziness, Some(u32::MAX), i64::MAX)),
+            bad_time
+        );
+        assert_eq!(
+            corrupt(|game| {
+                let pos = game.get_index(0, 0);
+                game.spawn_item(pos, 0);
+                game.refreshes.items[0].expires_at = -1;
+            }),
+            bad_time
+        );
+    }
+}
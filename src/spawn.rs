@@ -0,0 +1,305 @@
+use crate::Cell;
+use rand::{Rng, RngCore};
+use wasm_bindgen::prelude::*;
+
+/// What a strategy can see when choosing where new items go
+pub struct SpawnContext<'a> {
+    pub rows: usize,
+    pub cols: usize,
+    pub cells: &'a [Cell],
+    /// 还在场上的蛇的头部 (row, col)
+    pub heads: &'a [(i32, i32)],
+    pub wrap: bool,
+}
+
+impl SpawnContext<'_> {
+    #[inline]
+    fn position(&self, index: usize) -> (i32, i32) {
+        ((index / self.cols) as i32, (index % self.cols) as i32)
+    }
+
+    // 两个格子之间的曼哈顿距离，穿墙模式下可以绕过去
+    fn distance(&self, a: (i32, i32), b: (i32, i32)) -> usize {
+        let d_row = (a.0 - b.0).unsigned_abs() as usize;
+        let d_col = (a.1 - b.1).unsigned_abs() as usize;
+        if self.wrap {
+            d_row.min(self.rows - d_row) + d_col.min(self.cols - d_col)
+        } else {
+            d_row + d_col
+        }
+    }
+
+    // 上下左右相邻的格子
+    fn neighbors(&self, index: usize) -> impl Iterator<Item = usize> + '_ {
+        let (row, col) = self.position(index);
+        [(-1, 0), (1, 0), (0, -1), (0, 1)]
+            .iter()
+            .filter_map(move |&(d_row, d_col)| {
+                let (mut row, mut col) = (row + d_row, col + d_col);
+                if self.wrap {
+                    row = row.rem_euclid(self.rows as i32);
+                    col = col.rem_euclid(self.cols as i32);
+                }
+                if row < 0 || col < 0 || row >= self.rows as i32 || col >= self.cols as i32 {
+                    None
+                } else {
+                    Some(row as usize * self.cols + col as usize)
+                }
+            })
+    }
+
+    fn free_cells(&self) -> Vec<usize> {
+        (0..self.cells.len())
+            .filter(|&index| self.cells[index] == Cell::Blank)
+            .collect()
+    }
+}
+
+/// Chooses the cells new items are spawned on
+///
+/// 返回的格子必须是互不相同的空白格子，空白格子不够时可以少于 `count` 个
+pub trait SpawnStrategy {
+    fn pick(&self, ctx: &SpawnContext<'_>, count: usize, rng: &mut dyn RngCore) -> Vec<usize>;
+}
+
+// 把随机选出的最多 count 个换到 candidates 前面 (部分 Fisher-Yates 洗牌)，返回选出的个数
+fn shuffle_front(candidates: &mut [usize], count: usize, rng: &mut dyn RngCore) -> usize {
+    let count = count.min(candidates.len());
+    for i in 0..count {
+        let j = rng.gen_range(i, candidates.len());
+        candidates.swap(i, j);
+    }
+    count
+}
+
+fn choose(mut candidates: Vec<usize>, count: usize, rng: &mut dyn RngCore) -> Vec<usize> {
+    let count = shuffle_front(&mut candidates, count, rng);
+    candidates.truncate(count);
+    candidates
+}
+
+/// 所有空白格子等概率
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Uniform;
+
+impl SpawnStrategy for Uniform {
+    fn pick(&self, ctx: &SpawnContext<'_>, count: usize, rng: &mut dyn RngCore) -> Vec<usize> {
+        choose(ctx.free_cells(), count, rng)
+    }
+}
+
+/// 离每个蛇头至少 `min_distance` 步，不会刷在蛇嘴边
+#[derive(Clone, Copy, Debug)]
+pub struct AwayFromHead {
+    pub min_distance: usize,
+}
+
+impl Default for AwayFromHead {
+    fn default() -> AwayFromHead {
+        AwayFromHead { min_distance: 5 }
+    }
+}
+
+impl SpawnStrategy for AwayFromHead {
+    fn pick(&self, ctx: &SpawnContext<'_>, count: usize, rng: &mut dyn RngCore) -> Vec<usize> {
+        let candidates = ctx
+            .free_cells()
+            .into_iter()
+            .filter(|&index| {
+                let pos = ctx.position(index);
+                ctx.heads
+                    .iter()
+                    .all(|&head| ctx.distance(pos, head) >= self.min_distance)
+            })
+            .collect();
+        choose(candidates, count, rng)
+    }
+}
+
+/// 只刷在蛇头能走到的地方 (从蛇头出发 flood fill，可以穿过物品)，
+/// 不会刷在被身体或障碍物围住的区域里
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Reachable;
+
+impl SpawnStrategy for Reachable {
+    fn pick(&self, ctx: &SpawnContext<'_>, count: usize, rng: &mut dyn RngCore) -> Vec<usize> {
+        let mut seen = vec![false; ctx.cells.len()];
+        let mut stack: Vec<usize> = ctx
+            .heads
+            .iter()
+            .map(|&(row, col)| row as usize * ctx.cols + col as usize)
+            .collect();
+        for &index in &stack {
+            seen[index] = true;
+        }
+        let mut candidates = Vec::new();
+        while let Some(index) = stack.pop() {
+            for next in ctx.neighbors(index) {
+                let cell = ctx.cells[next];
+                if !seen[next] && (cell == Cell::Blank || cell.is_eatable()) {
+                    seen[next] = true;
+                    stack.push(next);
+                    if cell == Cell::Blank {
+                        candidates.push(next);
+                    }
+                }
+            }
+        }
+        // 按下标排序，结果只取决于随机数而不是搜索顺序
+        candidates.sort_unstable();
+        choose(candidates, count, rng)
+    }
+}
+
+/// 成堆地刷: 随机选一个中心，尽量刷在中心 `radius` 格以内，不够再选下一个中心
+#[derive(Clone, Copy, Debug)]
+pub struct Clustered {
+    pub radius: usize,
+}
+
+impl Default for Clustered {
+    fn default() -> Clustered {
+        Clustered { radius: 2 }
+    }
+}
+
+impl SpawnStrategy for Clustered {
+    fn pick(&self, ctx: &SpawnContext<'_>, count: usize, rng: &mut dyn RngCore) -> Vec<usize> {
+        let mut free = ctx.free_cells();
+        let mut picked = Vec::with_capacity(count);
+        while picked.len() < count && !free.is_empty() {
+            let center = free.swap_remove(rng.gen_range(0, free.len()));
+            picked.push(center);
+            let center = ctx.position(center);
+            let (mut near, far): (Vec<usize>, Vec<usize>) = free.into_iter().partition(|&index| {
+                let (row, col) = ctx.position(index);
+                (row - center.0).unsigned_abs() as usize <= self.radius
+                    && (col - center.1).unsigned_abs() as usize <= self.radius
+            });
+            let chosen = shuffle_front(&mut near, count - picked.len(), rng);
+            picked.extend(near.drain(..chosen));
+            // 没被选中的留给下一个中心
+            free = far;
+            free.append(&mut near);
+        }
+        picked
+    }
+}
+
+/// Built-in strategies selectable from `GameConfig`
+#[wasm_bindgen]
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SpawnStrategyKind {
+    Uniform = 0,
+    AwayFromHead = 1,
+    Reachable = 2,
+    Clustered = 3,
+}
+
+impl SpawnStrategyKind {
+    pub(crate) fn from_u8(id: u8) -> Option<SpawnStrategyKind> {
+        Some(match id {
+            0 => SpawnStrategyKind::Uniform,
+            1 => SpawnStrategyKind::AwayFromHead,
+            2 => SpawnStrategyKind::Reachable,
+            3 => SpawnStrategyKind::Clustered,
+            _ => return None,
+        })
+    }
+
+    /// 使用默认参数的策略
+    pub fn strategy(self) -> Box<dyn SpawnStrategy> {
+        match self {
+            SpawnStrategyKind::Uniform => Box::new(Uniform),
+            SpawnStrategyKind::AwayFromHead => Box::new(AwayFromHead::default()),
+            SpawnStrategyKind::Reachable => Box::new(Reachable),
+            SpawnStrategyKind::Clustered => Box::new(Clustered::default()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::rng::GameRng;
+    use crate::spawn::*;
+    use crate::Cell;
+
+    const STRATEGIES: [SpawnStrategyKind; 4] = [
+        SpawnStrategyKind::Uniform,
+        SpawnStrategyKind::AwayFromHead,
+        SpawnStrategyKind::Reachable,
+        SpawnStrategyKind::Clustered,
+    ];
+
+    fn context<'a>(cells: &'a [Cell], heads: &'a [(i32, i32)]) -> SpawnContext<'a> {
+        SpawnContext {
+            rows: 8,
+            cols: 8,
+            cells,
+            heads,
+            wrap: false,
+        }
+    }
+
+    #[test]
+    fn test_spawn_on_blank_cells() {
+        let mut rng = GameRng::new(0);
+        let mut cells = vec![Cell::Blank; 64];
+        cells[0] = Cell::HeadDispenser;
+        let heads = [(0, 0)];
+        for &kind in STRATEGIES.iter() {
+            let picked = kind.strategy().pick(&context(&cells, &heads), 5, &mut rng);
+            assert_eq!(picked.len(), 5, "{:?}", kind);
+            for (i, &pos) in picked.iter().enumerate() {
+                assert_eq!(cells[pos], Cell::Blank);
+                assert!(!picked[..i].contains(&pos));
+            }
+        }
+
+        // 空白格子不够时少刷几个，不会死循环
+        let mut cells = vec![Cell::ObstacleBedrock; 64];
+        cells[0] = Cell::HeadDispenser;
+        cells[1] = Cell::Blank;
+        cells[8] = Cell::Blank;
+        for &kind in STRATEGIES.iter() {
+            let mut picked = kind.strategy().pick(&context(&cells, &heads), 5, &mut rng);
+            picked.sort_unstable();
+            match kind {
+                SpawnStrategyKind::AwayFromHead => assert!(picked.is_empty()),
+                _ => assert_eq!(picked, [1, 8], "{:?}", kind),
+            }
+        }
+    }
+
+    #[test]
+    fn test_spawn_rules() {
+        let mut rng = GameRng::new(0);
+        let heads = [(3, 3)];
+
+        let cells = vec![Cell::Blank; 64];
+        let ctx = context(&cells, &heads);
+        for pos in AwayFromHead::default().pick(&ctx, 5, &mut rng) {
+            let (row, col) = ((pos / 8) as i32, (pos % 8) as i32);
+            assert!((row - 3).abs() + (col - 3).abs() >= 5);
+        }
+        let picked = Clustered { radius: 1 }.pick(&ctx, 5, &mut rng);
+        let (row, col) = (picked[0] / 8, picked[0] % 8);
+        // 中心周围 3x3 有 8 个空白格子，够放下其余 4 个
+        for &pos in &picked[1..] {
+            assert!((pos / 8).abs_diff(row) <= 1 && (pos % 8).abs_diff(col) <= 1);
+        }
+
+        // 第 5 列是一堵身体，右边的格子走不到
+        let mut cells = vec![Cell::Blank; 64];
+        for row in 0..8 {
+            cells[row * 8 + 5] = Cell::BodyStone;
+        }
+        cells[3 * 8 + 3] = Cell::HeadDispenser;
+        cells[3 * 8 + 4] = Cell::FoodBread;
+        let ctx = context(&cells, &heads);
+        let picked = Reachable.pick(&ctx, 64, &mut rng);
+        assert_eq!(picked.len(), 8 * 5 - 2);
+        assert!(picked.iter().all(|&pos| pos % 8 < 5));
+    }
+}
@@ -1,3 +1,4 @@
+#[cfg(target_arch = "wasm32")]
 use web_sys::console;
 
 pub fn set_panic_hook() {
@@ -12,12 +13,15 @@ pub fn set_panic_hook() {
 }
 
 // time profiler
+// console 只在浏览器中可用，native 下 (测试、命令行工具) 什么都不做
 pub struct Timer<'a> {
+    #[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
     name: &'a str,
 }
 
 impl<'a> Timer<'a> {
     pub fn new(name: &'a str) -> Timer<'a> {
+        #[cfg(target_arch = "wasm32")]
         console::time_with_label(name);
         Timer { name }
     }
@@ -25,6 +29,7 @@ impl<'a> Timer<'a> {
 
 impl<'a> Drop for Timer<'a> {
     fn drop(&mut self) {
+        #[cfg(target_arch = "wasm32")]
         console::time_end_with_label(self.name);
     }
 }